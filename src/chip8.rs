@@ -1,571 +1,1172 @@
-use std::f32::consts::PI as PI32;
-use std::f64::consts::PI as PI64;
-use std::time::Instant;
+#[cfg(feature = "egui")]
+pub mod debug_ui;
+pub mod display;
+pub mod instruction;
+pub mod keypad;
+pub mod quirks;
+pub mod timing;
 
-use crate::video;
-use crate::{emulator::Emulator, video::Screen};
+use std::collections::VecDeque;
+
+use crate::emulator::{self, Button, ButtonState, Emulator, Rgba};
+use crate::error::EmuError;
+use crate::memory_viewer::MemoryView;
+use crate::save_state::{self, SaveState, StateError, StateReader, StateWriter};
+use display::Display;
+use instruction::Instruction;
+use keypad::Keypad;
+use quirks::{Chip8Quirks, Chip8Variant};
 use rand::Rng;
-use sdl2::audio::{AudioCallback, AudioSpec, AudioSpecDesired, AudioStatus};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::{self, Color, PixelFormatEnum};
-use sdl2::rect::{Point, Rect};
-
-struct SineWave {
-    freq: f32,
-    amp: f32,
-    phase: f32,       // kinda like time apparently? got study more :)
-    sample_rate: f32, // amount of points of wave per second
+
+struct Cpu {
+    regs: [u8; 16],
+    pc: u16,
+    mem_address: u16, // TODO: Funções para monipulação, segundo a wikipedia, é um número com 12 bits, então o valor máximo seria 4095
 }
 
-impl AudioCallback for SineWave {
-    type Channel = f32;
+pub struct Chip8Emulator {
+    cpu: Cpu,
+    // 4 KiB, 64 KiB on the xo-chip
+    memory: Vec<u8>,
+    stack: [u16; 16],
+    sp: usize,
+    dt: usize,
+    st: usize,
+    display: Display,
+    // 00FD ran, nothing runs after it
+    exited: bool,
+    keypad: Keypad,
+    rom_hash: u64,
+    // kept for reset
+    rom: Vec<u8>,
+    framebuffer: Vec<Rgba>,
+    // the beeper plays these 128 bits over and over, pitch sets how fast
+    pattern: [u8; 16],
+    pitch: u8,
+    // made a frame at a time
+    samples: Vec<f32>,
+    // where in the pattern the beeper is, in bits
+    phase: f32,
+    // address and opcode of the last instructions ran, newest last
+    history: VecDeque<(u16, u16)>,
+    // kept for reset
+    variant: Chip8Variant,
+    pub quirks: Chip8Quirks,
+    // the schip's RPL flags, they outlive the game. The frontend keeps them on disk
+    flags: [u8; 16],
+    // instructions a second, spread over the frames
+    pub hz: u32,
+    // what didn't add up to a whole instruction last frame, in 60ths of one
+    hz_leftover: u32,
+    // runs as many instructions a frame as the VIP had time for, instead of hz
+    pub vip_timing: bool,
+    // machine cycles left this frame with vip timing, below 0 when the last instruction ran over
+    cycles: i32,
+    // the last instruction skipped the next one, which costs more on the vip
+    skipped: bool,
+}
+
+// prints every instruction as it runs
+const DEBUG: bool = false;
+const FRAME_RATE: f64 = 60.;
+// 12 instructions a frame, about what most games expect
+pub const DEFAULT_HZ: u32 = 720;
+// 8 bits on and 8 off at 4000 bits a second, a 250 Hz square like a beeper
+const DEFAULT_PATTERN: [u8; 16] = [
+    0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00,
+];
+const DEFAULT_PITCH: u8 = 64;
+const BEEP_AMP: f32 = 0.25; // I sure hope this doesn't explode my laptop's speakers
+const HISTORY_LEN: usize = 16;
+// roms get loaded here, everything under it was the interpreter's
+const PROGRAM_START: u16 = 0x200;
+// black and white, then the colors for pixels on the second plane and on both
+const PALETTE: [Rgba; 4] = [
+    Rgba::BLACK,
+    Rgba::WHITE,
+    Rgba::new(0xFF, 0x66, 0x00),
+    Rgba::new(0x66, 0x22, 0x00),
+];
+// 4x5 digits, 0 to F
+const SMALL_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+// 8x10 digits right after the small ones. 0 to 9 are the schip's, it had no letters, so A to F
+// are octo's like xo-chip roms expect
+const BIG_FONT_START: u16 = 0x50;
+const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
 
-    fn callback(&mut self, out: &mut [Self::Channel]) {
-        for x in out.iter_mut() {
-            *x = ((self.phase * PI32 * 2.0).sin() * self.amp) as f32;
-            println!("new wave: {}", *x);
-            // here, instead of adjust the pitch by multiplying the time * pi * freq, we make the phaser, or "timer" go faster
-            self.phase = (self.phase + self.freq / self.sample_rate) % 1.0;
+impl Cpu {
+    pub fn new() -> Cpu {
+        Cpu {
+            regs: [0; 16],
+            pc: PROGRAM_START,
+            mem_address: 0,
         }
     }
 }
 
-struct SquareWave {
-    freq: f32,
-    amp: f32,
-    phase: f32,       // kinda like time apparently? got study more :)
-    sample_rate: f32, // amount of points of wave per second
+// anything past the end of memory wraps around to the start, mask is its size - 1
+fn wrap(address: u16, mask: u16) -> usize {
+    (address & mask) as usize
+}
+
+// 5XY2 and 5XY3 go from X to Y, backwards when Y is smaller
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    if x <= y {
+        (x as usize..=y as usize).collect()
+    } else {
+        (y as usize..=x as usize).rev().collect()
+    }
+}
+
+impl Default for Chip8Emulator {
+    fn default() -> Chip8Emulator {
+        Chip8Emulator::new()
+    }
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
+impl Chip8Emulator {
+    pub fn new() -> Chip8Emulator {
+        Chip8Emulator::with_variant(Chip8Variant::CosmacVip)
+    }
+
+    // the quirks come from the variant, they can still be changed one by one afterwards
+    pub fn with_variant(variant: Chip8Variant) -> Chip8Emulator {
+        // addresses are 12 bits, the xo-chip's are 16
+        let memory_size = if variant == Chip8Variant::XoChip {
+            0x10000
+        } else {
+            0x1000
+        };
+        let mut memory = vec![0; memory_size];
+        memory[..SMALL_FONT.len()].copy_from_slice(&SMALL_FONT);
+        let big_font = BIG_FONT_START as usize;
+        memory[big_font..big_font + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
 
-    fn callback(&mut self, out: &mut [Self::Channel]) {
-        for x in out.iter_mut() {
-            *x = ((self.phase * PI32 * 2.0).sin() * self.amp) as f32;
-            if *x > 0. {
-                *x = self.amp;
+        Chip8Emulator {
+            cpu: Cpu::new(),
+            memory,
+            stack: [0; 16],
+            sp: 0,
+            dt: 0,
+            st: 0,
+            display: Display::new(),
+            exited: false,
+            keypad: Keypad::default(),
+            rom_hash: save_state::rom_hash(&[]),
+            rom: vec![],
+            framebuffer: vec![Rgba::BLACK; 64 * 32],
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            samples: vec![],
+            phase: 0.,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            variant,
+            quirks: Chip8Quirks::for_variant(variant),
+            flags: [0; 16],
+            hz: DEFAULT_HZ,
+            hz_leftover: 0,
+            vip_timing: false,
+            cycles: 0,
+            skipped: false,
+        }
+    }
+
+    fn address_mask(&self) -> u16 {
+        (self.memory.len() - 1) as u16
+    }
+
+    fn word(&self, address: u16) -> u16 {
+        let mask = self.address_mask();
+        u16::from_be_bytes([
+            self.memory[wrap(address, mask)],
+            self.memory[wrap(address.wrapping_add(1), mask)],
+        ])
+    }
+
+    // the sound timer plays the pattern for as long as it's not 0
+    fn beep(&mut self) {
+        let count = emulator::SAMPLE_RATE as f64 / FRAME_RATE;
+        // 4000 bits a second at 64, an octave up every 48
+        let rate = 4000. * 2f32.powf((self.pitch as f32 - 64.) / 48.);
+        for _ in 0..count as usize {
+            let bit = self.phase as usize;
+            let sample = if self.st == 0 {
+                0.
+            } else if self.pattern[bit / 8] >> (7 - bit % 8) & 1 != 0 {
+                BEEP_AMP
+            } else {
+                -BEEP_AMP
+            };
+            self.samples.push(sample);
+            self.phase = (self.phase + rate / emulator::SAMPLE_RATE as f32) % 128.;
+        }
+    }
+
+    fn update_framebuffer(&mut self) {
+        let (width, height) = self.display.size();
+        self.framebuffer.resize(width * height, Rgba::BLACK);
+        for (at, pixel) in self.framebuffer.iter_mut().enumerate() {
+            *pixel = PALETTE[self.display.color(at % width, at / width)];
+        }
+    }
+
+    fn run_instructions(&mut self) -> Result<(), EmuError> {
+        // what the last frame ran over comes out of this one
+        self.cycles += timing::CYCLES_PER_FRAME;
+        let frames = FRAME_RATE as u32;
+        let instructions = (self.hz + self.hz_leftover) / frames;
+        self.hz_leftover = (self.hz + self.hz_leftover) % frames;
+        let mut count = 0;
+        loop {
+            let done = if self.vip_timing {
+                self.cycles <= 0
             } else {
-                *x = -self.amp;
+                count == instructions
+            };
+            if done || self.exited {
+                break;
+            }
+            count += 1;
+            let pc = self.cpu.pc;
+            let op = self.word(pc);
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back((pc, op));
+            let instruction = Instruction::decode(op);
+            if DEBUG {
+                println!("{pc:04X}: {op:04X}  {instruction}");
+            }
+
+            let mut cycles = timing::vip_cycles(instruction, &self.cpu.regs);
+            self.skip();
+            self.skipped = false;
+            if let Err(err) = self.execute(instruction, pc, op) {
+                // stays on the instruction that failed
+                self.cpu.pc = pc;
+                return Err(err);
+            }
+            if self.skipped {
+                cycles += timing::SKIP_TAKEN;
             }
-            self.phase = (self.phase + self.freq / self.sample_rate) % 1.0;
+            self.cycles -= cycles as i32;
+            if self.quirks.display_wait && matches!(instruction, Instruction::Draw { .. }) {
+                // it waits for the display interrupt, and a new frame starts after it
+                self.cycles = 0;
+                break;
+            }
+        }
+        if !self.vip_timing {
+            self.cycles = 0;
         }
+        Ok(())
     }
-}
 
-struct Cpu {
-    regs: [u8; 16],
-    ip: usize,
-    mem_address: u16, // TODO: Funções para monipulação, segundo a wikipedia, é um número com 12 bits, então o valor máximo seria 4095
-}
+    // past a word, how pc moves past the instruction it just fetched
+    fn skip(&mut self) {
+        self.cpu.pc = self.cpu.pc.wrapping_add(2) & self.address_mask();
+    }
 
-pub struct Chip8Emulator {
-    cpu: Cpu,
-    memory: [u8; 4096],
-    stack: [u16; 16],
-    sp: usize,
-    dt: usize,
-    st: usize,
-}
+    // past the next instruction, which is two words when it's the xo-chip's F000 NNNN
+    fn skip_next(&mut self) {
+        let long = self.variant == Chip8Variant::XoChip && self.word(self.cpu.pc) == 0xF000;
+        self.skip();
+        if long {
+            self.skip();
+        }
+        self.skipped = true;
+    }
 
-impl Emulator for Chip8Emulator {
-    fn run(&mut self, rom: &[u8]) {
-        let pixel_size = 16;
-        let mut screen = Screen::new(Some(64 * pixel_size), Some(32 * pixel_size));
-        let mut screen_bits = [false; 64 * 32];
-
-        self.memory[512..512 + rom.len()].copy_from_slice(&rom);
-        let mut cur_pressed_keys = [false; 16];
-
-        let audio_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1), // mono channel
-            samples: None,
-        };
+    // the quirks can be set to anything, but there's only room for 16
+    fn stack_depth(&self) -> usize {
+        self.quirks.stack_depth.min(self.stack.len())
+    }
 
-        let audio_device = screen
-            .audio
-            .open_playback(None, &audio_spec, |spec| {
-                SquareWave {
-                    freq: 440., // should be an a
-                    phase: 0.,  // kinda like start of timer
-                    amp: 0.25,  // I sure hope this doesn't explode my laptop's speakers
-                    sample_rate: spec.freq as f32,
-                }
-            })
-            .unwrap();
-
-        audio_device.pause();
-
-        'main_loop: loop {
-            // the timers go down one each 1/60 of a second
-            self.dt = self.dt.saturating_sub(1);
-            self.st = self.st.saturating_sub(1);
-
-            if audio_device.status() == AudioStatus::Paused && self.st > 0 {
-                // sound timer was set to something
-                audio_device.resume();
-            }
-
-            if audio_device.status() == AudioStatus::Playing && self.st == 0 {
-                // sound timer arrived at 0
-                audio_device.pause();
-            }
-
-            for event in screen.event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. } => break 'main_loop,
-                    Event::KeyDown { keycode, .. } => {
-                        if let Some(keycode) = keycode {
-                            match keycode {
-                                Keycode::NUM_1 => cur_pressed_keys[1] = true,
-                                Keycode::NUM_2 => cur_pressed_keys[2] = true,
-                                Keycode::NUM_3 => cur_pressed_keys[3] = true,
-                                Keycode::Q => cur_pressed_keys[4] = true,
-                                Keycode::W => cur_pressed_keys[5] = true,
-                                Keycode::E => cur_pressed_keys[6] = true,
-                                Keycode::A => cur_pressed_keys[7] = true,
-                                Keycode::S => cur_pressed_keys[8] = true,
-                                Keycode::D => cur_pressed_keys[9] = true,
-                                Keycode::X => cur_pressed_keys[0] = true,
-                                Keycode::Z => cur_pressed_keys[10] = true,
-                                Keycode::C => cur_pressed_keys[11] = true,
-                                Keycode::NUM_4 => cur_pressed_keys[12] = true,
-                                Keycode::R => cur_pressed_keys[13] = true,
-                                Keycode::F => cur_pressed_keys[14] = true,
-                                Keycode::V => cur_pressed_keys[15] = true,
-                                _ => {}
-                            }
-                        }
-                    }
-                    Event::KeyUp { keycode, .. } => {
-                        if let Some(keycode) = keycode {
-                            match keycode {
-                                Keycode::NUM_1 => cur_pressed_keys[1] = false,
-                                Keycode::NUM_2 => cur_pressed_keys[2] = false,
-                                Keycode::NUM_3 => cur_pressed_keys[3] = false,
-                                Keycode::Q => cur_pressed_keys[4] = false,
-                                Keycode::W => cur_pressed_keys[5] = false,
-                                Keycode::E => cur_pressed_keys[6] = false,
-                                Keycode::A => cur_pressed_keys[7] = false,
-                                Keycode::S => cur_pressed_keys[8] = false,
-                                Keycode::D => cur_pressed_keys[9] = false,
-                                Keycode::X => cur_pressed_keys[0] = false,
-                                Keycode::Z => cur_pressed_keys[10] = false,
-                                Keycode::C => cur_pressed_keys[11] = false,
-                                Keycode::NUM_4 => cur_pressed_keys[12] = false,
-                                Keycode::R => cur_pressed_keys[13] = false,
-                                Keycode::F => cur_pressed_keys[14] = false,
-                                Keycode::V => cur_pressed_keys[15] = false,
-                                _ => {}
-                            }
-                        }
+    // pc already points at the next instruction, the one running was at pc
+    fn execute(&mut self, instruction: Instruction, pc: u16, op: u16) -> Result<(), EmuError> {
+        let quirks = self.quirks;
+        let stack_depth = self.stack_depth();
+        let schip = self.variant.has_schip();
+        let xochip = self.variant == Chip8Variant::XoChip;
+        let mask = self.address_mask();
+        let regs = &mut self.cpu.regs;
+        match instruction {
+            Instruction::Clear => self.display.clear(),
+            Instruction::ScrollDown { n } if schip => self.display.scroll(0, n as isize),
+            Instruction::ScrollUp { n } if xochip => self.display.scroll(0, -(n as isize)),
+            Instruction::ScrollRight if schip => self.display.scroll(4, 0),
+            Instruction::ScrollLeft if schip => self.display.scroll(-4, 0),
+            Instruction::Exit if schip => self.exited = true,
+            Instruction::Lores if schip => self.display.set_hires(false),
+            Instruction::Hires if schip => self.display.set_hires(true),
+            Instruction::Return => {
+                if self.sp == 0 {
+                    if !quirks.wrap_stack {
+                        return Err(EmuError::StackUnderflow { pc });
                     }
-                    _ => {}
+                    self.sp = stack_depth;
                 }
+                self.sp -= 1;
+                self.cpu.pc = self.stack[self.sp];
             }
-            screen.canvas.set_draw_color(Color::BLACK);
-            screen.canvas.clear();
-            let mut rects = vec![];
-            for pixel_y in 0..32 {
-                for pixel_x in 0..64 {
-                    if screen_bits[pixel_x + pixel_y * 64] {
-                        let rect = Rect::new(
-                            pixel_x as i32 * pixel_size as i32,
-                            pixel_y as i32 * pixel_size as i32,
-                            pixel_size,
-                            pixel_size,
-                        );
-                        rects.push(rect);
+            Instruction::Jump(nnn) => self.cpu.pc = nnn,
+            Instruction::Call(nnn) => {
+                if self.sp >= stack_depth {
+                    if !quirks.wrap_stack {
+                        let calls = self.stack[..stack_depth]
+                            .iter()
+                            .map(|address| address.wrapping_sub(2))
+                            .collect();
+                        return Err(EmuError::StackOverflow { pc, calls });
                     }
+                    self.sp = 0;
                 }
+                self.stack[self.sp] = self.cpu.pc;
+                self.sp += 1;
+                self.cpu.pc = nnn;
             }
-            screen.canvas.set_draw_color(Color::WHITE);
-            match screen.canvas.fill_rects(&rects) {
-                Ok(_) => {}
-                Err(err) => panic!("{}", err),
-            }
-            screen.canvas.present();
-
-            print!("{:04X}: ", self.cpu.ip + 512);
-            let frame_start = Instant::now();
-
-            for _ in 0..750 / 60 {
-                let hex1 = format!("{:02X}", rom[self.cpu.ip]).chars().nth(0).unwrap();
-                let hex2 = format!("{:02X}", rom[self.cpu.ip]).chars().nth(1).unwrap();
-                let hex3 = format!("{:02X}", rom[self.cpu.ip + 1])
-                    .chars()
-                    .nth(0)
-                    .unwrap();
-                let hex4 = format!("{:02X}", rom[self.cpu.ip + 1])
-                    .chars()
-                    .nth(1)
-                    .unwrap();
-
-                match (hex1, hex2, hex3, hex4) {
-                    ('0', '0', 'E', '0') => {
-                        screen_bits = [false; 64 * 32];
-                        println!("[CLRS]")
-                    }
-                    ('0', '0', 'E', 'E') => {
-                        println!("[RET]");
-                        self.sp -= 1;
-                        self.cpu.ip = self.stack[self.sp] as usize;
-                    }
-                    ('1', n1, n2, n3) => {
-                        self.cpu.ip =
-                            usize::from_str_radix(format!("{}{}{}", n1, n2, n3).as_str(), 16)
-                                .unwrap()
-                                - 512;
-                        // The game code should be in memory, so we subtract its offset
-                        // Maybe will have to move the game code into memory in the future
-                        println!("[JUMP]: {:?}", self.cpu.ip);
-                        continue; // does not increment counter
-                    }
-                    ('2', n1, n2, n3) => {
-                        self.stack[self.sp] = self.cpu.ip as u16;
-                        self.sp += 1;
-                        self.cpu.ip =
-                            usize::from_str_radix(format!("{}{}{}", n1, n2, n3).as_str(), 16)
-                                .unwrap()
-                                - 512;
-                        // * divided by two because a instruction is two bytes?
-                        println!("[CALL] {}", self.cpu.ip);
-                        continue; // does not increment counter
-                    }
-                    ('3', r, n1, n2) => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        println!(
-                            "[IIE ]: V{r} {} == {}",
-                            self.cpu.regs[reg_i], self.cpu.regs[reg_i]
-                        ); // Ignore if not equal
-                        let value_to_check =
-                            u8::from_str_radix(format!("{}{}", n1, n2).as_str(), 16).unwrap();
-                        if self.cpu.regs[reg_i] == value_to_check {
-                            self.cpu.ip += 2;
-                            println!("        [INFO]: Ignored.");
-                        }
-                    }
-                    ('4', r, n1, n2) => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        let value_to_check =
-                            u8::from_str_radix(format!("{}{}", n1, n2).as_str(), 16).unwrap();
-                        println!(
-                            "[IINE]: V{} {} != {}",
-                            reg_i, self.cpu.regs[reg_i], value_to_check
-                        ); // Ignore if equal
-                        if self.cpu.regs[reg_i] != value_to_check {
-                            self.cpu.ip += 2;
-                            println!("  [INFO]: Ignored.");
-                        }
-                    }
-                    ('5', n1, n2, '0') => {
-                        let reg_i1 = usize::from_str_radix(n1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(n2.to_string().as_str(), 16).unwrap();
-                        println!(
-                            "[IIRE]: V{reg_i1} == V{reg_i2} | {} == {}",
-                            self.cpu.regs[reg_i1], self.cpu.regs[reg_i2]
-                        ); // Ignore if registers are equal
-                        if self.cpu.regs[reg_i1] == self.cpu.regs[reg_i2] {
-                            self.cpu.ip += 2;
-                            println!("  [INFO]: Ignored.");
-                        }
-                    }
-                    ('6', r, n1, n2) => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        let value_to_set =
-                            u8::from_str_radix(format!("{}{}", n1, n2).as_str(), 16).unwrap();
-                        self.cpu.regs[reg_i] = value_to_set;
-                        println!("[MSET]: {} at V{}", self.cpu.regs[reg_i], reg_i);
-                    }
-                    ('7', r, n1, n2) => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        let value_to_add =
-                            u8::from_str_radix(format!("{}{}", n1, n2).as_str(), 16).unwrap();
-                        self.cpu.regs[reg_i] = self.cpu.regs[reg_i].wrapping_add(value_to_add);
-                        println!("[INCR]: V{reg_i} += {value_to_add}");
-                    }
-                    ('8', r1, r2, '0') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-                        println!("[CPXY]: V{r1} = V{r2} = {}", self.cpu.regs[reg_i2]);
-                        self.cpu.regs[reg_i1] = self.cpu.regs[reg_i2];
-                    }
-                    ('8', r1, r2, '1') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-                        println!(
-                            "[ORR ]: V{r1} | V{r2} = {:08b} | {:08b} = {:08b}",
-                            self.cpu.regs[reg_i1],
-                            self.cpu.regs[reg_i2],
-                            self.cpu.regs[reg_i1] | self.cpu.regs[reg_i2]
-                        );
-                        self.cpu.regs[reg_i1] = self.cpu.regs[reg_i2] | self.cpu.regs[reg_i1];
-                    }
-                    ('8', r1, r2, '2') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-                        println!(
-                            "[ANDR]: V{r1} = V{r1} & V{r2} = {:08b} & {:08b} = {:08b}",
-                            self.cpu.regs[reg_i1],
-                            self.cpu.regs[reg_i2],
-                            self.cpu.regs[reg_i1] & self.cpu.regs[reg_i2]
-                        );
-                        self.cpu.regs[reg_i1] = self.cpu.regs[reg_i1] & self.cpu.regs[reg_i2];
-                    }
-                    ('8', r1, r2, '3') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-                        println!(
-                            "[XORR]: V{r1} = V{r1} ^ V{r2} = {:08b} ^ {:08b} = {:08b}",
-                            self.cpu.regs[reg_i1],
-                            self.cpu.regs[reg_i2],
-                            self.cpu.regs[reg_i1] ^ self.cpu.regs[reg_i2]
-                        );
-                        self.cpu.regs[reg_i1] = self.cpu.regs[reg_i1] ^ self.cpu.regs[reg_i2];
-                    }
-                    ('8', r1, r2, '4') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-
-                        let sum = self.cpu.regs[reg_i1].overflowing_add(self.cpu.regs[reg_i2]);
-                        self.cpu.regs[reg_i1] = sum.0;
-                        self.cpu.regs[15] = match sum.1 {
-                            true => 1,
-                            false => 0,
-                        };
-                        println!("[ADDC]");
-                    }
-                    ('8', r1, r2, '5') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-
-                        let sub = self.cpu.regs[reg_i1].overflowing_sub(self.cpu.regs[reg_i2]);
-                        self.cpu.regs[reg_i1] = sub.0;
-                        self.cpu.regs[15] = match sub.1 {
-                            true => 0,
-                            false => 1,
-                        };
-                        println!("[SUBC]"); // sub wrap carry if not borrow
-                    }
-                    ('8', r1, _r2, '6') => {
-                        let reg_i = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        if self.cpu.regs[reg_i] & 0b1 == 1 {
-                            self.cpu.regs[15] = 1;
-                        } else {
-                            self.cpu.regs[15] = 0;
-                        }
-                        self.cpu.regs[reg_i] = self.cpu.regs[reg_i] >> 1;
-                        println!("[SRCL]: V{r1} VF = {}", self.cpu.regs[15]);
-                    }
-                    ('8', r1, _r2, 'E') => {
-                        let reg_i = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        if self.cpu.regs[reg_i] & 0b10000000 == 0b10000000 {
-                            self.cpu.regs[15] = 1;
-                        } else {
-                            self.cpu.regs[15] = 0;
-                        }
-                        self.cpu.regs[reg_i] = self.cpu.regs[reg_i] << 1;
-                        println!("[SLCM]: V{r1} VF = {}", self.cpu.regs[15]);
-                    }
-                    ('9', r1, r2, '0') => {
-                        let reg_i1 = usize::from_str_radix(r1.to_string().as_str(), 16).unwrap();
-                        let reg_i2 = usize::from_str_radix(r2.to_string().as_str(), 16).unwrap();
-                        println!("[SINE]: {reg_i1} != {reg_i2}");
-                        if self.cpu.regs[reg_i1] != self.cpu.regs[reg_i2] {
-                            println!("      [INFO]: Ignored.");
-                            self.cpu.ip += 2;
-                        }
-                    }
-                    ('A', n1, n2, n3) => {
-                        self.cpu.mem_address =
-                            u16::from_str_radix(format!("{}{}{}", n1, n2, n3).as_str(), 16)
-                                .unwrap();
-                        println!("[MPST]: {}", self.cpu.mem_address);
-                    }
-                    ('C', r, k1, k2) => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        let mut rng = rand::thread_rng();
-                        let value_to_and =
-                            u8::from_str_radix(format!("{}{}", k1, k2).as_str(), 16).unwrap();
-                        let n: u8 = rng.gen::<u8>() & value_to_and;
-                        self.cpu.regs[reg_i] = n;
-                        println!("[RAND]: {r}");
-                    }
-                    ('D', x, y, n) => {
-                        let height = usize::from_str_radix(n.to_string().as_str(), 16).unwrap();
-                        let reg_x = u8::from_str_radix(x.to_string().as_str(), 16).unwrap();
-                        let reg_y = u8::from_str_radix(y.to_string().as_str(), 16).unwrap();
-                        let pos_x = self.cpu.regs[reg_x as usize] as usize;
-                        let pos_y = self.cpu.regs[reg_y as usize] as usize;
-
-                        self.cpu.regs[15] = 0;
-
-                        // TODO: Handle screen wraping
-                        for mut y in pos_y..pos_y + height {
-                            let cur_line = self.memory[self.cpu.mem_address as usize + y - pos_y];
-                            // trying my best to not do the most convoluted mess ever written
-                            for mut x in pos_x..pos_x + 8 {
-                                let mut local_x = x - pos_x;
-                                local_x = 7 - local_x;
-
-                                let sprite_pixel = ((cur_line >> local_x) & 1) != 0;
-                                x = x % 64;
-                                y = y % 32;
-                                let cur_screen_pixel = screen_bits[x + y * 64];
-                                let new_pixel = (sprite_pixel || cur_screen_pixel)
-                                    && !(sprite_pixel && cur_screen_pixel);
-                                screen_bits[x + y * 64] = new_pixel;
-                                if cur_screen_pixel == true && new_pixel == false {
-                                    self.cpu.regs[15] = 1; // collision acontecey
-                                }
-                            }
-                        }
-
-                        println!("[DSA]");
-                    }
-                    ('E', r, '9', 'E') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        println!("[SIKP]: Checking {}", self.cpu.regs[reg_i]);
-                        if cur_pressed_keys[self.cpu.regs[reg_i] as usize] {
-                            self.cpu.ip += 2;
-                            println!("        [INFO]: Skipped")
-                        }
-                    }
-                    ('E', r, 'A', '1') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        println!("[SINP]: Checking {}", self.cpu.regs[reg_i]); // skip if not pressed
-                        if !cur_pressed_keys[self.cpu.regs[reg_i] as usize] {
-                            self.cpu.ip += 2;
-                            println!("        [INFO]: Skipped")
-                        }
-                    }
-                    ('F', r, '0', '7') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        self.cpu.regs[reg_i] = self.dt as u8;
-                        println!("[SRDT]: V{reg_i} = DT = {}", self.dt);
-                    }
-                    ('F', r, '0', 'A') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        println!("[WFI ]: V{reg_i}");
-                        let mut was_pressed = false;
-                        for (value, pressed) in cur_pressed_keys.iter().enumerate() {
-                            if *pressed {
-                                self.cpu.regs[reg_i] = value as u8;
-                                was_pressed = true;
-                            }
-                        }
-                        if !was_pressed {
-                            continue;
-                        }
-                    }
-                    ('F', r, '1', '5') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        self.dt = self.cpu.regs[reg_i] as usize;
-                        println!("[SDTR]: DT = V{reg_i} = {}", self.dt);
-                    }
-                    ('F', n, '1', '8') => {
-                        self.st = self.cpu.regs
-                            [usize::from_str_radix(n.to_string().as_str(), 16).unwrap() as usize]
-                            as usize;
-                        println!("[STST]: Sound timer = {}", self.st);
-                    }
-                    ('F', r, '1', 'E') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        self.cpu.mem_address += self.cpu.regs[reg_i] as u16;
-                        println!("[ADDI]: {} from V{reg_i}", self.cpu.regs[reg_i]);
-                    }
-                    ('F', r, '2', '9') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        let n = self.cpu.regs[reg_i];
-                        self.cpu.mem_address = n as u16 * 5;
-                        println!("[SITD]: {n} new address = {}", self.cpu.mem_address);
-                    }
-                    ('F', r, '3', '3') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        let mem_address = self.cpu.mem_address as usize;
-                        let to_text = format!("{:03}", self.cpu.regs[reg_i]);
-                        self.memory[mem_address] =
-                            to_text.chars().nth(0).unwrap().to_digit(10).unwrap() as u8;
-                        self.memory[mem_address + 1] =
-                            to_text.chars().nth(1).unwrap().to_digit(10).unwrap() as u8;
-                        self.memory[mem_address + 2] =
-                            to_text.chars().nth(2).unwrap().to_digit(10).unwrap() as u8;
-                        println!("[DBCD]: V{reg_i} to {}", self.cpu.mem_address);
-                        println!(
-                            "  [INFO]: Value = {}, in memory = {} {} {}",
-                            self.cpu.regs[reg_i],
-                            self.memory[mem_address],
-                            self.memory[mem_address + 1],
-                            self.memory[mem_address + 2]
-                        );
-                    }
-                    ('F', r, '5', '5') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        for i in 0..=reg_i {
-                            self.memory[self.cpu.mem_address as usize + i] = self.cpu.regs[i];
-                        }
-                        println!("[RDMP]: V{reg_i} at {}", self.cpu.mem_address);
-                    }
-                    ('F', r, '6', '5') => {
-                        let reg_i = usize::from_str_radix(r.to_string().as_str(), 16).unwrap();
-                        for i in 0..=reg_i {
-                            // self.memory[self.cpu.mem_address as usize + i] = self.cpu.regs[i];
-                            self.cpu.regs[i] =
-                                self.memory[(self.cpu.mem_address + i as u16) as usize];
-                        }
-                        println!("[RRD ]: To V{reg_i} at {}", self.cpu.mem_address);
-                        // ! WASN'T WORKING CORRECTLY BECAUSE THE EMULATOR DIDN'T DUMP THE ROM INTO THE RAM, SO IT JUST COPIED A BUNCH OF ZEROS
-                    }
-                    _ => todo!("HEX: {}{}{}{}\nIP: {}", hex1, hex2, hex3, hex4, self.cpu.ip),
+            Instruction::SkipEqual { x, nn } => {
+                if regs[x as usize] == nn {
+                    self.skip_next();
+                }
+            }
+            Instruction::SkipNotEqual { x, nn } => {
+                if regs[x as usize] != nn {
+                    self.skip_next();
+                }
+            }
+            Instruction::SkipEqualReg { x, y } => {
+                if regs[x as usize] == regs[y as usize] {
+                    self.skip_next();
+                }
+            }
+            Instruction::Set { x, nn } => regs[x as usize] = nn,
+            Instruction::Add { x, nn } => regs[x as usize] = regs[x as usize].wrapping_add(nn),
+            Instruction::Copy { x, y } => regs[x as usize] = regs[y as usize],
+            Instruction::Or { x, y } | Instruction::And { x, y } | Instruction::Xor { x, y } => {
+                match instruction {
+                    Instruction::Or { .. } => regs[x as usize] |= regs[y as usize],
+                    Instruction::And { .. } => regs[x as usize] &= regs[y as usize],
+                    _ => regs[x as usize] ^= regs[y as usize],
+                }
+                if quirks.logic_resets_vf {
+                    regs[15] = 0;
+                }
+            }
+            Instruction::AddReg { x, y } => {
+                let (sum, carry) = regs[x as usize].overflowing_add(regs[y as usize]);
+                regs[x as usize] = sum;
+                regs[15] = carry as u8;
+            }
+            // sub wrap carry if not borrow
+            Instruction::Sub { x, y } => {
+                let (difference, borrow) = regs[x as usize].overflowing_sub(regs[y as usize]);
+                regs[x as usize] = difference;
+                regs[15] = !borrow as u8;
+            }
+            Instruction::SubReverse { x, y } => {
+                let (difference, borrow) = regs[y as usize].overflowing_sub(regs[x as usize]);
+                regs[x as usize] = difference;
+                regs[15] = !borrow as u8;
+            }
+            Instruction::ShiftRight { x, y } => {
+                let value = regs[if quirks.shift_uses_vy { y } else { x } as usize];
+                regs[x as usize] = value >> 1;
+                regs[15] = value & 1;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let value = regs[if quirks.shift_uses_vy { y } else { x } as usize];
+                regs[x as usize] = value << 1;
+                regs[15] = value >> 7;
+            }
+            Instruction::SkipNotEqualReg { x, y } => {
+                if regs[x as usize] != regs[y as usize] {
+                    self.skip_next();
+                }
+            }
+            Instruction::StoreRange { x, y } if xochip => {
+                for (offset, reg) in register_range(x, y).into_iter().enumerate() {
+                    self.memory[wrap(self.cpu.mem_address.wrapping_add(offset as u16), mask)] =
+                        regs[reg];
+                }
+            }
+            Instruction::LoadRange { x, y } if xochip => {
+                for (offset, reg) in register_range(x, y).into_iter().enumerate() {
+                    regs[reg] =
+                        self.memory[wrap(self.cpu.mem_address.wrapping_add(offset as u16), mask)];
+                }
+            }
+            Instruction::SetIndex(nnn) => self.cpu.mem_address = nnn,
+            Instruction::SetIndexLong if xochip => {
+                self.cpu.mem_address = self.word(self.cpu.pc);
+                self.skip();
+            }
+            Instruction::JumpOffset(nnn) => {
+                // BXNN, X is the top nibble of the address
+                let offset = if quirks.jump_uses_vx {
+                    regs[(nnn >> 8) as usize]
+                } else {
+                    regs[0]
+                };
+                self.cpu.pc = nnn.wrapping_add(offset as u16) & mask;
+            }
+            Instruction::Random { x, nn } => regs[x as usize] = rand::thread_rng().gen::<u8>() & nn,
+            Instruction::Draw { x, y, n } => {
+                // DXY0 is 16x16, two bytes a row
+                let (rows, columns) = if n == 0 && schip {
+                    (16, 16)
+                } else {
+                    (n as usize, 8)
+                };
+                let row_bytes = columns / 8;
+                // with both planes selected the second one's sprite comes right after the first's
+                let mut sprite_at = self.cpu.mem_address;
+                let (mut collided, mut counted) = (false, 0);
+                for plane in self.display.selected_planes() {
+                    let sprite: Vec<u16> = (0..rows)
+                        .map(|row| {
+                            let at = sprite_at.wrapping_add((row * row_bytes) as u16);
+                            (0..row_bytes).fold(0, |line, byte| {
+                                line << 8
+                                    | self.memory[wrap(at.wrapping_add(byte as u16), mask)] as u16
+                            })
+                        })
+                        .collect();
+                    let (vx, vy) = (regs[x as usize], regs[y as usize]);
+                    let collisions =
+                        self.display
+                            .draw(plane, vx, vy, &sprite, columns, quirks.clip_sprites);
+                    collided |= collisions.rows > 0; // collision acontecey
+                    counted += collisions.rows + collisions.clipped;
+                    sprite_at = sprite_at.wrapping_add((rows * row_bytes) as u16);
                 }
-                self.cpu.ip += 2;
+                regs[15] = if quirks.count_collision_rows && self.display.hires() {
+                    counted as u8
+                } else {
+                    collided as u8
+                };
+            }
+            Instruction::SkipPressed { x } => {
+                if self.keypad.held(regs[x as usize]) {
+                    self.skip_next();
+                }
+            }
+            Instruction::SkipNotPressed { x } => {
+                if !self.keypad.held(regs[x as usize]) {
+                    self.skip_next();
+                }
+            }
+            Instruction::Plane { n } if xochip => self.display.select(n),
+            Instruction::Pattern if xochip => {
+                for (offset, byte) in self.pattern.iter_mut().enumerate() {
+                    *byte =
+                        self.memory[wrap(self.cpu.mem_address.wrapping_add(offset as u16), mask)];
+                }
+            }
+            Instruction::GetDelay { x } => regs[x as usize] = self.dt as u8,
+            // a key pressed and let go, or this again until there is one
+            Instruction::WaitKey { x } => match self.keypad.wait() {
+                Some(key) => regs[x as usize] = key,
+                None => self.cpu.pc = pc,
+            },
+            Instruction::SetDelay { x } => self.dt = regs[x as usize] as usize,
+            Instruction::SetSound { x } => self.st = regs[x as usize] as usize,
+            Instruction::AddIndex { x } => {
+                self.cpu.mem_address = self.cpu.mem_address.wrapping_add(regs[x as usize] as u16)
+            }
+            Instruction::Font { x } => self.cpu.mem_address = (regs[x as usize] & 0xF) as u16 * 5,
+            Instruction::BigFont { x } if schip => {
+                self.cpu.mem_address = BIG_FONT_START + (regs[x as usize] & 0xF) as u16 * 10
+            }
+            Instruction::Pitch { x } if xochip => self.pitch = regs[x as usize],
+            Instruction::Bcd { x } => {
+                let value = regs[x as usize];
+                let digits = [value / 100, value / 10 % 10, value % 10];
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    self.memory[wrap(self.cpu.mem_address.wrapping_add(offset as u16), mask)] =
+                        digit;
+                }
+            }
+            Instruction::Store { x } => {
+                for (offset, value) in regs[..=x as usize].iter().enumerate() {
+                    self.memory[wrap(self.cpu.mem_address.wrapping_add(offset as u16), mask)] =
+                        *value;
+                }
+                self.cpu.mem_address = self
+                    .cpu
+                    .mem_address
+                    .wrapping_add(quirks.index_increment.amount(x));
+            }
+            Instruction::Load { x } => {
+                for (offset, value) in regs[..=x as usize].iter_mut().enumerate() {
+                    *value =
+                        self.memory[wrap(self.cpu.mem_address.wrapping_add(offset as u16), mask)];
+                }
+                self.cpu.mem_address = self
+                    .cpu
+                    .mem_address
+                    .wrapping_add(quirks.index_increment.amount(x));
+            }
+            Instruction::SaveFlags { x } if schip => {
+                self.flags[..=x as usize].copy_from_slice(&regs[..=x as usize]);
             }
-            while frame_start.elapsed().as_millis() < 1000 / 60 {}
+            Instruction::LoadFlags { x } if schip => {
+                regs[..=x as usize].copy_from_slice(&self.flags[..=x as usize])
+            }
+            // the schip and xo-chip ones land here on the machines without them
+            _ => return Err(EmuError::UnknownOpcode { pc, op }),
         }
+        Ok(())
     }
 }
 
-impl Cpu {
-    pub fn new() -> Cpu {
-        Cpu {
-            regs: [0; 16],
-            ip: 0,
-            mem_address: 0,
+impl Emulator for Chip8Emulator {
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmuError> {
+        let max = self.memory.len() - PROGRAM_START as usize;
+        if rom.len() > max {
+            return Err(EmuError::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
         }
+        let start = PROGRAM_START as usize;
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.rom_hash = save_state::rom_hash(rom);
+        self.rom = rom.to_vec();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), EmuError> {
+        let mut reset = Chip8Emulator::with_variant(self.variant);
+        reset.quirks = self.quirks;
+        reset.hz = self.hz;
+        reset.vip_timing = self.vip_timing;
+        reset.load_rom(&self.rom)?;
+        reset.flags = self.flags;
+        *self = reset;
+        Ok(())
+    }
+
+    // one 60th of a second worth of instructions
+    fn run_frame(&mut self) -> Result<(), EmuError> {
+        // the timers go down one each 1/60 of a second, however many instructions that is
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+
+        let result = self.run_instructions();
+        self.beep();
+        self.update_framebuffer();
+        result
+    }
+
+    fn framebuffer(&self) -> (&[Rgba], usize, usize) {
+        let (width, height) = self.display.size();
+        (&self.framebuffer, width, height)
+    }
+
+    fn audio_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    fn set_input(&mut self, buttons: ButtonState) {
+        let keys = std::array::from_fn(|key| buttons.pressed(Button::Keypad(key as u8)));
+        self.keypad.update(keys);
+    }
+
+    fn frame_rate(&self) -> f64 {
+        FRAME_RATE
+    }
+
+    fn trace(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .map(|(pc, op)| format!("{pc:04X}: {op:04X}  {}", Instruction::decode(*op)))
+            .collect()
     }
 }
 
-impl Chip8Emulator {
-    pub fn new() -> Chip8Emulator {
-        let mut memory = [0; 4096];
-        memory[0..80].copy_from_slice(&[
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ]);
+const MACHINE: &str = "Chip8";
 
-        Chip8Emulator {
-            cpu: Cpu::new(),
-            memory,
-            stack: [0; 16],
-            sp: 0,
-            dt: 0,
-            st: 0,
+impl SaveState for Chip8Emulator {
+    fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    // only the schip ones have them
+    fn flags(&self) -> Option<Vec<u8>> {
+        self.variant.has_schip().then(|| self.flags.to_vec())
+    }
+
+    fn load_flags(&mut self, flags: &[u8]) {
+        let len = flags.len().min(self.flags.len());
+        self.flags[..len].copy_from_slice(&flags[..len]);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(MACHINE, self.rom_hash);
+        state.chunk(b"CPU ", |chunk| {
+            chunk.bytes(&self.cpu.regs);
+            chunk.u16(self.cpu.pc);
+            chunk.u16(self.cpu.mem_address);
+            for address in self.stack {
+                chunk.u16(address);
+            }
+            chunk.u8(self.sp as u8);
+            chunk.u8(self.dt as u8);
+            chunk.u8(self.st as u8);
+        });
+        state.chunk(b"MEM ", |chunk| chunk.bytes(&self.memory));
+        // only as many pixels as the resolution has, so lores states look like they always did
+        state.chunk(b"SCRN", |chunk| chunk.bytes(&self.display.plane_bytes(0)));
+        state.chunk(b"SCHP", |chunk| {
+            chunk.bool(self.display.hires());
+            chunk.bool(self.exited);
+            chunk.bytes(&self.flags);
+        });
+        state.chunk(b"KEYS", |chunk| chunk.u8(self.keypad.wait_state()));
+        state.chunk(b"XOCH", |chunk| {
+            chunk.u8(self.display.selected());
+            chunk.bytes(&self.display.plane_bytes(1));
+            chunk.bytes(&self.pattern);
+            chunk.u8(self.pitch);
+        });
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let state = StateReader::parse(state, MACHINE, self.rom_hash)?;
+
+        // everything goes into a new machine first, so a broken state doesn't leave it half loaded
+        let mut loaded = Chip8Emulator::with_variant(self.variant);
+        loaded.quirks = self.quirks;
+        let mut cpu = state.chunk(b"CPU ")?;
+        cpu.bytes_into(&mut loaded.cpu.regs)?;
+        // older states counted from where the rom starts
+        let start = if state.version < 2 { PROGRAM_START } else { 0 };
+        loaded.cpu.pc = cpu.u16()?.wrapping_add(start);
+        loaded.cpu.mem_address = cpu.u16()?;
+        for address in loaded.stack.iter_mut() {
+            *address = cpu.u16()?.wrapping_add(start);
+        }
+        loaded.sp = cpu.u8()? as usize;
+        // deeper than this machine's stack goes, like a schip state on the vip
+        if loaded.sp > loaded.stack_depth() {
+            return Err(StateError::Corrupt("CPU ".to_string()));
+        }
+        loaded.dt = cpu.u8()? as usize;
+        loaded.st = cpu.u8()? as usize;
+        state.chunk(b"MEM ")?.bytes_into(&mut loaded.memory)?;
+        // older states are from before the schip, they're lores with no flags
+        loaded.flags = self.flags;
+        if let Ok(mut schip) = state.chunk(b"SCHP") {
+            loaded.display.set_hires(schip.bool()?);
+            loaded.exited = schip.bool()?;
+            schip.bytes_into(&mut loaded.flags)?;
+        }
+        let (width, height) = loaded.display.size();
+        let mut bits = vec![0; width * height];
+        state.chunk(b"SCRN")?.bytes_into(&mut bits)?;
+        loaded.display.load_plane(0, &bits);
+        // and from before the xo-chip, with one plane and the default sound
+        if let Ok(mut xochip) = state.chunk(b"XOCH") {
+            loaded.display.select(xochip.u8()?);
+            xochip.bytes_into(&mut bits)?;
+            loaded.display.load_plane(1, &bits);
+            xochip.bytes_into(&mut loaded.pattern)?;
+            loaded.pitch = xochip.u8()?;
+        }
+
+        loaded.hz = self.hz;
+        loaded.vip_timing = self.vip_timing;
+        loaded.keypad = self.keypad;
+        // states from before it are never in the middle of FX0A, it didn't wait for a release
+        let wait = match state.chunk(b"KEYS") {
+            Ok(mut keys) => keys.u8()?,
+            Err(_) => 0,
+        };
+        loaded.keypad.set_wait_state(wait);
+        loaded.rom_hash = self.rom_hash;
+        loaded.rom = std::mem::take(&mut self.rom);
+        loaded.phase = self.phase;
+        loaded.update_framebuffer();
+        *self = loaded;
+        Ok(())
+    }
+}
+
+impl MemoryView for Chip8Emulator {
+    fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn peek(&self, address: usize) -> u8 {
+        self.memory[address]
+    }
+
+    fn poke(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+    }
+
+    fn region(&self, address: usize) -> String {
+        match address {
+            0x000..=0x04F => "FONT",
+            0x050..=0x0EF => "BIGFONT",
+            // where the interpreter itself lived on the real machines
+            0x0F0..=0x1FF => "INTERP",
+            _ => "PROGRAM",
         }
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chip8_round_trip() {
+        // V0 = 5, sprite for 5, draw it
+        let rom = [0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        let state = chip8.save_state();
+
+        let mut other = Chip8Emulator::new();
+        other.load_rom(&rom).unwrap();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        assert!(matches!(
+            Chip8Emulator::new().load_state(&state),
+            Err(StateError::WrongRom)
+        ));
+    }
+
+    #[test]
+    fn stops_on_errors() {
+        let mut chip8 = Chip8Emulator::new();
+        assert!(matches!(
+            chip8.load_rom(&[0; 4096]),
+            Err(EmuError::RomTooLarge {
+                size: 4096,
+                max: 0xE00
+            })
+        ));
+
+        // V0 = 1, then nothing that exists
+        chip8.load_rom(&[0x60, 0x01, 0x5F, 0xF1]).unwrap();
+        assert!(matches!(
+            chip8.run_frame(),
+            Err(EmuError::UnknownOpcode {
+                pc: 0x202,
+                op: 0x5FF1
+            })
+        ));
+        assert_eq!(
+            chip8.trace(),
+            ["0200: 6001  LD V0, 01", "0202: 5FF1  DW 5FF1"]
+        );
+
+        // calls itself forever
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&[0x22, 0x00]).unwrap();
+        match (0..2).find_map(|_| chip8.run_frame().err()) {
+            Some(EmuError::StackOverflow { pc: 0x200, calls }) => assert_eq!(calls, [0x200; 12]),
+            other => panic!("expected a stack overflow, got {other:?}"),
+        }
+        // the others have room for 16
+        let mut chip8 = Chip8Emulator::with_variant(Chip8Variant::Schip);
+        chip8.load_rom(&[0x22, 0x00]).unwrap();
+        assert!(matches!(
+            (0..2).find_map(|_| chip8.run_frame().err()),
+            Some(EmuError::StackOverflow { calls, .. }) if calls.len() == 16
+        ));
+        chip8.quirks.wrap_stack = true;
+        chip8.reset().unwrap();
+        for _ in 0..4 {
+            chip8.run_frame().unwrap();
+        }
+        // 48 calls, around twice and full again
+        assert_eq!(chip8.sp, 16);
+
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&[0x00, 0xEE]).unwrap();
+        assert!(matches!(
+            chip8.run_frame(),
+            Err(EmuError::StackUnderflow { pc: 0x200 })
+        ));
+        // returns to whatever the top of the stack had, just the one instruction
+        chip8.quirks.wrap_stack = true;
+        chip8.hz = 60;
+        chip8.run_frame().unwrap();
+        assert_eq!((chip8.cpu.pc, chip8.sp), (0, 11));
+    }
+
+    #[test]
+    fn runs_code_from_memory() {
+        // writes `V3 = 2A, jump 20E` to 0x100 and jumps there, 20E loops
+        let rom = [
+            0x60, 0x63, 0x61, 0x2A, 0x62, 0x12, 0x63, 0x0E, 0xA1, 0x00, 0xF3, 0x55, 0x11, 0x00,
+            0x12, 0x0E,
+        ];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.memory[0x100..0x104], [0x63, 0x2A, 0x12, 0x0E]);
+        assert_eq!(chip8.cpu.regs[3], 0x2A);
+        assert_eq!(chip8.cpu.pc, 0x20E);
+
+        // 123 at the very end of memory, the last digit wraps to the start
+        let rom = [0x60, 123, 0xAF, 0xFE, 0xF0, 0x33, 0x12, 0x06];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.memory[0xFFE..], [1, 2]);
+        assert_eq!(chip8.memory[0], 3);
+    }
+
+    #[test]
+    fn follows_the_quirks() {
+        // V2 |= V1 keeping VF in V4, shift V0 with V1, store V0-V1 at 300, then BNNN or BXNN
+        let mut rom = vec![
+            0x60, 0x03, 0x61, 0x10, 0x6F, 0x05, 0x82, 0x11, 0x84, 0xF0, 0x80, 0x16, 0xA3, 0x00,
+            0xF1, 0x55, 0xB2, 0x20,
+        ];
+        rom.resize(0x32, 0);
+        // where each jump lands, looping there
+        rom[0x28..0x2A].copy_from_slice(&[0x12, 0x28]);
+        rom[0x30..0x32].copy_from_slice(&[0x12, 0x30]);
+
+        let mut vip = Chip8Emulator::with_variant(Chip8Variant::CosmacVip);
+        vip.load_rom(&rom).unwrap();
+        vip.run_frame().unwrap();
+        assert_eq!(vip.cpu.regs[4], 0);
+        assert_eq!(vip.cpu.regs[0], 0x08);
+        assert_eq!(vip.cpu.mem_address, 0x302);
+        assert_eq!(vip.cpu.pc, 0x228);
+
+        let mut schip = Chip8Emulator::with_variant(Chip8Variant::Schip);
+        schip.load_rom(&rom).unwrap();
+        schip.run_frame().unwrap();
+        assert_eq!(schip.cpu.regs[4], 5);
+        assert_eq!(schip.cpu.regs[0], 0x01);
+        assert_eq!(schip.cpu.mem_address, 0x300);
+        assert_eq!(schip.cpu.pc, 0x230);
+
+        let mut chip48 = Chip8Emulator::with_variant(Chip8Variant::Chip48);
+        chip48.load_rom(&rom).unwrap();
+        chip48.run_frame().unwrap();
+        assert_eq!(chip48.cpu.mem_address, 0x301);
+
+        // a 0 at x 62, half of it past the edge
+        let rom = [0xA0, 0x00, 0x60, 0x3E, 0x61, 0x00, 0xD0, 0x15, 0x12, 0x08];
+        vip.load_rom(&rom).unwrap();
+        vip.reset().unwrap();
+        vip.run_frame().unwrap();
+        assert!(vip.display.pixel(0, 62, 0) && !vip.display.pixel(0, 0, 0));
+        // waited for the next frame right after drawing
+        assert_eq!(vip.cpu.pc, 0x208);
+        assert_eq!(vip.history.len(), 4);
+
+        let mut xo_chip = Chip8Emulator::with_variant(Chip8Variant::XoChip);
+        xo_chip.load_rom(&rom).unwrap();
+        xo_chip.run_frame().unwrap();
+        assert!(xo_chip.display.pixel(0, 62, 0) && xo_chip.display.pixel(0, 0, 0));
+    }
+
+    #[test]
+    fn runs_schip_roms() {
+        // hires, a 16x16 block at 64,32 scrolled down 2 and right 4, the big 5, then the flags
+        let mut rom = vec![
+            0x00, 0xFF, 0x60, 0x40, 0x61, 0x20, 0xA2, 0x40, 0xD0, 0x10, 0x00, 0xC2, 0x00, 0xFB,
+            0x62, 0x05, 0xF2, 0x30, 0xF2, 0x75, 0x60, 0x00, 0xF0, 0x85, 0x00, 0xFD, 0x12, 0x00,
+        ];
+        rom.resize(0x40, 0);
+        rom.extend([0xFF; 32]);
+
+        let mut chip8 = Chip8Emulator::with_variant(Chip8Variant::Schip);
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        chip8.run_frame().unwrap();
+        let (pixels, width, height) = chip8.framebuffer();
+        assert_eq!((pixels.len(), width, height), (128 * 64, 128, 64));
+        let pixel = |x: usize, y: usize| chip8.display.pixel(0, x, y);
+        assert!(pixel(68, 34) && pixel(83, 49));
+        assert!(!pixel(67, 34) && !pixel(68, 33) && !pixel(84, 49));
+        assert_eq!(chip8.cpu.regs[15], 0);
+        assert_eq!(chip8.cpu.mem_address, BIG_FONT_START + 50);
+        assert_eq!(chip8.flags().unwrap()[..3], [0x40, 0x20, 5]);
+        assert_eq!(chip8.cpu.regs[0], 0x40);
+        // exited, nothing runs anymore
+        assert_eq!(chip8.cpu.pc, 0x21A);
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.cpu.pc, 0x21A);
+
+        let state = chip8.save_state();
+        let mut loaded = Chip8Emulator::with_variant(Chip8Variant::Schip);
+        loaded.load_rom(&rom).unwrap();
+        loaded.load_state(&state).unwrap();
+        assert!(loaded.display.hires() && loaded.exited);
+        assert_eq!(loaded.display, chip8.display);
+        assert_eq!(loaded.flags, chip8.flags);
+
+        // the vip never had any of it
+        let mut vip = Chip8Emulator::new();
+        vip.load_rom(&rom).unwrap();
+        assert_eq!(vip.flags(), None);
+        assert!(matches!(
+            vip.run_frame(),
+            Err(EmuError::UnknownOpcode {
+                pc: 0x200,
+                op: 0x00FF
+            })
+        ));
+
+        // the big F, drawn at 0,0
+        let rom = [0x63, 0x0F, 0xF3, 0x30, 0x60, 0x00, 0xD0, 0x0A, 0x12, 0x08];
+        let mut xo_chip = Chip8Emulator::with_variant(Chip8Variant::XoChip);
+        xo_chip.load_rom(&rom).unwrap();
+        xo_chip.run_frame().unwrap();
+        assert_eq!(xo_chip.cpu.mem_address, BIG_FONT_START + 150);
+        assert_eq!(xo_chip.region(0xE6), "BIGFONT");
+        assert_eq!(xo_chip.region(0xF0), "INTERP");
+        let column =
+            |x: usize| -> Vec<bool> { (0..10).map(|y| xo_chip.display.pixel(0, x, y)).collect() };
+        assert_eq!(column(0), [true; 10]);
+        assert_eq!(
+            column(7),
+            [true, true, false, false, true, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn runs_xo_chip_roms() {
+        // V1 and V0 out to 2000 and back, a pixel on both planes at 0,1, the second plane scrolled
+        // up, the sound, then a skip over F000 NNNN
+        let mut rom = vec![
+            0x60, 0x11, 0x61, 0x22, 0xF0, 0x00, 0x20, 0x00, 0x51, 0x02, 0x60, 0x00, 0x50, 0x13,
+            0xF3, 0x01, 0xA3, 0x00, 0x62, 0x01, 0x63, 0x00, 0xD3, 0x21, 0xF2, 0x01, 0x00, 0xD1,
+            0xA3, 0x02, 0xF0, 0x02, 0x64, 0x50, 0xF4, 0x3A, 0x34, 0x50, 0xF0, 0x00, 0x00, 0x00,
+            0x12, 0x2A,
+        ];
+        rom.resize(0x100, 0);
+        rom.extend([0x80, 0x80]);
+        rom.extend([0xAA; 16]);
+
+        let mut chip8 = Chip8Emulator::with_variant(Chip8Variant::XoChip);
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.memory[0x2000..0x2002], [0x22, 0x11]);
+        assert_eq!(chip8.cpu.regs[..2], [0x22, 0x11]);
+        assert_eq!(chip8.cpu.pc, 0x22A);
+        assert_eq!(chip8.cpu.mem_address, 0x302);
+        let (pixels, _, _) = chip8.framebuffer();
+        assert_eq!(pixels[0], PALETTE[2]);
+        assert_eq!(pixels[64], PALETTE[1]);
+        assert_eq!(chip8.pattern, [0xAA; 16]);
+        assert_eq!(chip8.pitch, 0x50);
+
+        let state = chip8.save_state();
+        let mut loaded = Chip8Emulator::with_variant(Chip8Variant::XoChip);
+        loaded.load_rom(&rom).unwrap();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.display, chip8.display);
+        assert_eq!((loaded.display.selected(), loaded.pitch), (2, 0x50));
+    }
+
+    #[test]
+    fn runs_at_vip_speed() {
+        // V0 += 2 forever, an add at a time
+        let rom = [0x70, 0x01, 0x70, 0x01, 0x12, 0x00];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.vip_timing = true;
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        // 50 cycles an add and 52 the jump fit 12 times, and the next add runs over
+        assert_eq!(chip8.cpu.regs[0], 25);
+        assert_eq!(chip8.cycles, timing::CYCLES_PER_FRAME - 12 * 152 - 50);
+        // which the next frame has less time for
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.cpu.regs[0], 49);
+
+        // a draw ends the frame, however long it took
+        let rom = [0xD0, 0x1F, 0x12, 0x00];
+        chip8.load_rom(&rom).unwrap();
+        chip8.reset().unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!((chip8.cpu.pc, chip8.cycles), (0x202, 0));
+
+        // a jump that happens to land 4 bytes on is no skip, 36 jumps at 52 cycles
+        let rom = [0x12, 0x04, 0x00, 0x00, 0x12, 0x04];
+        chip8.load_rom(&rom).unwrap();
+        chip8.reset().unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.cycles, timing::CYCLES_PER_FRAME - 36 * 52);
+
+        // the xo-chip's skip over F000 NNNN is taken all the same, then 35 jumps
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x00, 0x00, 0x12, 0x06];
+        let mut chip8 = Chip8Emulator::with_variant(Chip8Variant::XoChip);
+        chip8.vip_timing = true;
+        chip8.load_rom(&rom).unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.cpu.pc, 0x206);
+        assert_eq!(chip8.cycles, timing::CYCLES_PER_FRAME - 54 - 35 * 52);
+    }
+
+    #[test]
+    fn runs_at_any_speed() {
+        // V0 += 1 and the delay timer at FF, then V1 = DT forever
+        let rom = [0x70, 0x01, 0x6F, 0xFF, 0xFF, 0x15, 0xF1, 0x07, 0x12, 0x06];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.hz = 90;
+        chip8.load_rom(&rom).unwrap();
+        // a frame and a half worth of instructions, so one every other frame
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.history.len(), 1);
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.history.len(), 3);
+
+        chip8.hz = 6000;
+        chip8.reset().unwrap();
+        for _ in 0..10 {
+            chip8.run_frame().unwrap();
+        }
+        // still ticks once a frame, the set counts as the first
+        assert_eq!(chip8.cpu.regs[1], 0xFF - 9);
+    }
+
+    #[test]
+    fn waits_for_a_key_to_be_let_go() {
+        // V1 = 1A, wait for V0, skip V2 = 1 when key A (the low nibble of V1) is held
+        let rom = [0x61, 0x1A, 0xF0, 0x0A, 0xE1, 0x9E, 0x62, 0x01, 0x12, 0x08];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&rom).unwrap();
+        let mut frame = |keys: &[u8]| {
+            let mut buttons = ButtonState::default();
+            for key in keys {
+                buttons.set(Button::Keypad(*key), true);
+            }
+            chip8.set_input(buttons);
+            chip8.run_frame().unwrap();
+            chip8.cpu.pc
+        };
+        // 3 was already held, and 5 doesn't count until it's let go
+        assert_eq!(frame(&[3]), 0x202);
+        assert_eq!(frame(&[3]), 0x202);
+        assert_eq!(frame(&[5]), 0x202);
+        assert_eq!(frame(&[0xA]), 0x208);
+        assert_eq!(chip8.cpu.regs[0], 5);
+        assert_eq!(chip8.cpu.regs[2], 0);
+    }
+
+    #[test]
+    fn counts_collision_rows_in_hires() {
+        // hires, the top 3 rows of the 0 at 0,62 with VF kept in V2, then again over itself
+        let rom = [
+            0x00, 0xFF, 0x60, 0x00, 0x61, 0x3E, 0xA0, 0x00, 0xD0, 0x13, 0x82, 0xF0, 0xD0, 0x13,
+            0x12, 0x0E,
+        ];
+        let mut schip = Chip8Emulator::with_variant(Chip8Variant::Schip);
+        schip.load_rom(&rom).unwrap();
+        // up to the first draw, which only has the bottom two rows to draw on
+        schip.hz = 5 * 60;
+        schip.run_frame().unwrap();
+        assert_eq!(schip.cpu.regs[15], 1);
+        assert!(schip.display.pixel(0, 0, 62) && schip.display.pixel(0, 0, 63));
+        assert!(!schip.display.pixel(0, 0, 0));
+        schip.run_frame().unwrap();
+        // the third row didn't fit, then the two that did hit the ones already there
+        assert_eq!(schip.cpu.regs[2], 1);
+        assert_eq!(schip.cpu.regs[15], 3);
+        assert!(!schip.display.pixel(0, 0, 62));
+
+        // the xo-chip wraps the third row around to the top, and only says whether it hit
+        let mut xo_chip = Chip8Emulator::with_variant(Chip8Variant::XoChip);
+        xo_chip.load_rom(&rom).unwrap();
+        xo_chip.hz = 5 * 60;
+        xo_chip.run_frame().unwrap();
+        assert_eq!(xo_chip.cpu.regs[15], 0);
+        assert!(xo_chip.display.pixel(0, 0, 62) && xo_chip.display.pixel(0, 0, 0));
+        xo_chip.run_frame().unwrap();
+        assert_eq!(xo_chip.cpu.regs[2], 0);
+        assert_eq!(xo_chip.cpu.regs[15], 1);
+        assert!(!xo_chip.display.pixel(0, 0, 0) && !xo_chip.display.pixel(0, 0, 62));
+
+        let mut display = Display::new();
+        display.set_hires(true);
+        let zero = [0xF0, 0x90, 0x90];
+        let collisions = display.draw(0, 0, 62, &zero, 8, true);
+        assert_eq!((collisions.rows, collisions.clipped), (0, 1));
+        let collisions = display.draw(0, 0, 62, &zero, 8, true);
+        assert_eq!((collisions.rows, collisions.clipped), (2, 1));
+
+        let mut display = Display::new();
+        let collisions = display.draw(0, 200, 40, &[0x80], 8, true);
+        assert_eq!(collisions, display::Collisions::default());
+        // where it starts wraps around, 200 % 64 and 40 % 32
+        assert!(display.pixel(0, 8, 8));
+    }
+
+    #[test]
+    fn refuses_states_deeper_than_the_stack() {
+        // 14 calls deep on the schip, which the vip has no room for
+        let rom = [0x22, 0x00];
+        let mut schip = Chip8Emulator::with_variant(Chip8Variant::Schip);
+        schip.load_rom(&rom).unwrap();
+        schip.hz = 14 * 60;
+        schip.run_frame().unwrap();
+        assert_eq!(schip.sp, 14);
+
+        let mut vip = Chip8Emulator::new();
+        vip.load_rom(&rom).unwrap();
+        assert!(matches!(
+            vip.load_state(&schip.save_state()),
+            Err(StateError::Corrupt(_))
+        ));
+
+        // and more than 16 is still 16
+        schip.quirks.stack_depth = 40;
+        schip.hz = 60;
+        schip.run_frame().unwrap();
+        schip.run_frame().unwrap();
+        assert!(matches!(
+            schip.run_frame(),
+            Err(EmuError::StackOverflow { calls, .. }) if calls.len() == 16
+        ));
     }
 }
@@ -0,0 +1,151 @@
+// Runs the chip8 inside an egui window instead of sdl, with the memory viewer next to the screen.
+// There's no sound in here, the beeper lives in the sdl frontend
+use eframe::egui::{self, Color32, Key, RichText};
+
+use super::Chip8Emulator;
+use crate::emulator::{Button, ButtonState, Emulator};
+use crate::error::{self, EmuError};
+use crate::memory_viewer::MemoryViewer;
+use crate::save_state::SaveState;
+
+// of a lores pixel, hires ones are half as big so the screen doesn't change size
+const PIXEL_SIZE: f32 = 8.0;
+
+// same layout as the sdl frontend, the left side of a qwerty keyboard
+const KEYS: [(Key, u8); 16] = [
+    (Key::Num1, 1),
+    (Key::Num2, 2),
+    (Key::Num3, 3),
+    (Key::Num4, 12),
+    (Key::Q, 4),
+    (Key::W, 5),
+    (Key::E, 6),
+    (Key::R, 13),
+    (Key::A, 7),
+    (Key::S, 8),
+    (Key::D, 9),
+    (Key::F, 14),
+    (Key::Z, 10),
+    (Key::X, 0),
+    (Key::C, 11),
+    (Key::V, 15),
+];
+
+struct DebugApp {
+    emulator: Chip8Emulator,
+    memory_viewer: MemoryViewer,
+    paused: bool,
+    // what stopped it, with the instructions before
+    error: Option<String>,
+}
+
+pub fn run(mut emulator: Chip8Emulator) -> Result<(), EmuError> {
+    emulator.read_flags_file();
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([1200.0, 640.0]),
+        ..Default::default()
+    };
+    let app = DebugApp {
+        emulator,
+        memory_viewer: MemoryViewer::new(),
+        paused: false,
+        error: None,
+    };
+    eframe::run_native("play-us chip8", options, Box::new(|_cc| Ok(Box::new(app))))
+        .map_err(|err| EmuError::Gui(err.to_string()))
+}
+
+fn mono(text: impl Into<String>) -> RichText {
+    RichText::new(text).monospace()
+}
+
+impl DebugApp {
+    fn run_frame(&mut self) {
+        self.memory_viewer.snapshot(&self.emulator);
+        let flags = self.emulator.flags();
+        if let Err(err) = self.emulator.run_frame() {
+            self.error = Some(error::report(&err, &self.emulator));
+            self.paused = true;
+        }
+        if self.emulator.flags() != flags {
+            self.emulator.write_flags_file();
+        }
+        // no sound in here, they'd just pile up
+        self.emulator.audio_samples();
+    }
+
+    fn screen(&self, ui: &mut egui::Ui) {
+        let size = egui::vec2(64.0 * PIXEL_SIZE, 32.0 * PIXEL_SIZE);
+        let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+        let origin = response.rect.min;
+        painter.rect_filled(response.rect, 0.0, Color32::BLACK);
+        let (pixels, width, _) = self.emulator.framebuffer();
+        let pixel_size = PIXEL_SIZE * 64.0 / width as f32;
+        for (at, pixel) in pixels.iter().enumerate() {
+            let (x, y) = (at % width, at / width);
+            let min = origin + egui::vec2(x as f32, y as f32) * pixel_size;
+            let rect = egui::Rect::from_min_size(min, egui::vec2(pixel_size, pixel_size));
+            painter.rect_filled(rect, 0.0, Color32::from_rgb(pixel.r, pixel.g, pixel.b));
+        }
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let emulator = &self.emulator;
+        egui::Grid::new("registers").show(ui, |ui| {
+            for (index, value) in emulator.cpu.regs.iter().enumerate() {
+                ui.label(mono(format!("V{index:X} {value:02X}")));
+                if index % 4 == 3 {
+                    ui.end_row();
+                }
+            }
+        });
+        ui.label(mono(format!(
+            "PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}",
+            emulator.cpu.pc, emulator.cpu.mem_address, emulator.sp, emulator.dt, emulator.st
+        )));
+    }
+}
+
+impl eframe::App for DebugApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // the memory viewer text boxes would type into the game otherwise
+        if !ctx.wants_keyboard_input() {
+            let mut buttons = ButtonState::default();
+            ctx.input(|input| {
+                for (key, index) in KEYS {
+                    buttons.set(Button::Keypad(index), input.key_down(key));
+                }
+            });
+            self.emulator.set_input(buttons);
+        }
+
+        if !self.paused {
+            self.run_frame();
+            ctx.request_repaint();
+        }
+
+        egui::SidePanel::right("memory")
+            .default_width(620.0)
+            .show(ctx, |ui| self.memory_viewer.show(ui, &mut self.emulator));
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let label = if self.paused { "Resume" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    self.paused = !self.paused;
+                }
+                if ui
+                    .add_enabled(self.paused, egui::Button::new("Step frame"))
+                    .clicked()
+                {
+                    self.run_frame();
+                }
+            });
+            self.screen(ui);
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::RED, mono(error));
+            }
+            ui.separator();
+            self.registers(ui);
+        });
+    }
+}
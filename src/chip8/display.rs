@@ -0,0 +1,156 @@
+// The chip8's screen: 64x32 pixels, or 128x64 in the schip's hires, and two bit planes that only
+// the xo-chip draws on the second of. A pixel's color is its bit on each plane, 0 to 3
+const MAX_PIXELS: usize = 128 * 64;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Display {
+    // row by row, only the first width * height of each are on screen
+    planes: [[bool; MAX_PIXELS]; 2],
+    hires: bool,
+    // which planes drawing, clearing and scrolling touch, a bit each
+    selected: u8,
+}
+
+// what drawing a sprite on one plane hit, rows that didn't hit anything aren't counted
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Collisions {
+    // rows with a pixel that was already on
+    pub rows: usize,
+    // rows that didn't fit below the bottom edge and got cut off
+    pub clipped: usize,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            planes: [[false; MAX_PIXELS]; 2],
+            hires: false,
+            selected: 1,
+        }
+    }
+
+    // width and height in the current resolution
+    pub fn size(&self) -> (usize, usize) {
+        if self.hires {
+            (128, 64)
+        } else {
+            (64, 32)
+        }
+    }
+
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    // switching resolution clears both planes, whichever are selected
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[false; MAX_PIXELS]; 2];
+    }
+
+    pub fn selected(&self) -> u8 {
+        self.selected
+    }
+
+    pub fn select(&mut self, planes: u8) {
+        self.selected = planes & 0b11;
+    }
+
+    pub fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let selected = self.selected;
+        (0..2).filter(move |plane| selected >> plane & 1 != 0)
+    }
+
+    pub fn pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        let (width, _) = self.size();
+        self.planes[plane][x + y * width]
+    }
+
+    // an index into the palette
+    pub fn color(&self, x: usize, y: usize) -> usize {
+        self.pixel(0, x, y) as usize | (self.pixel(1, x, y) as usize) << 1
+    }
+
+    pub fn clear(&mut self) {
+        for plane in self.selected_planes() {
+            self.planes[plane] = [false; MAX_PIXELS];
+        }
+    }
+
+    // moves the selected planes by dx and dy pixels, what comes in from the edges is blank
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.size();
+        for plane in self.selected_planes() {
+            let old = self.planes[plane];
+            for y in 0..height {
+                for x in 0..width {
+                    let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+                    let inside = (0..width as isize).contains(&from_x)
+                        && (0..height as isize).contains(&from_y);
+                    self.planes[plane][x + y * width] =
+                        inside && old[from_x as usize + from_y as usize * width];
+                }
+            }
+        }
+    }
+
+    // xors a sprite onto a plane, rows are columns bits wide with the leftmost in the top bit.
+    // Where it starts always wraps around the screen, what goes past the edges either gets cut
+    // off or comes back on the other side
+    pub fn draw(
+        &mut self,
+        plane: usize,
+        x: u8,
+        y: u8,
+        sprite: &[u16],
+        columns: usize,
+        clip: bool,
+    ) -> Collisions {
+        let (width, height) = self.size();
+        let (start_x, start_y) = (x as usize % width, y as usize % height);
+        let mut collisions = Collisions::default();
+        for (row, line) in sprite.iter().enumerate() {
+            let y = start_y + row;
+            if clip && y >= height {
+                collisions.clipped += 1;
+                continue;
+            }
+            let mut collided = false;
+            for column in 0..columns {
+                if (line >> (columns - 1 - column)) & 1 == 0 {
+                    continue;
+                }
+                let x = start_x + column;
+                if clip && x >= width {
+                    continue;
+                }
+                let at = x % width + y % height * width;
+                collided |= self.planes[plane][at];
+                self.planes[plane][at] ^= true;
+            }
+            collisions.rows += collided as usize;
+        }
+        collisions
+    }
+
+    // a byte a pixel in the current resolution, for save states
+    pub fn plane_bytes(&self, plane: usize) -> Vec<u8> {
+        let (width, height) = self.size();
+        self.planes[plane][..width * height]
+            .iter()
+            .map(|bit| *bit as u8)
+            .collect()
+    }
+
+    pub fn load_plane(&mut self, plane: usize, bytes: &[u8]) {
+        for (bit, byte) in self.planes[plane].iter_mut().zip(bytes) {
+            *bit = *byte != 0;
+        }
+    }
+}
@@ -0,0 +1,291 @@
+// Chip8 opcodes decoded into something the interpreter can match on. Decoding is just picking
+// nibbles out of the word, execution lives in chip8.rs, and the Display impl doubles as the
+// disassembler, in the Cowgod's reference syntax
+use std::fmt;
+
+// x and y are registers, n is a nibble, nn a byte and nnn an address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    // 00E0
+    Clear,
+    // 00EE
+    Return,
+    // 00CN, schip from here down to Exit
+    ScrollDown { n: u8 },
+    // 00DN, xo-chip
+    ScrollUp { n: u8 },
+    // 00FB, 4 pixels
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD, the interpreter quits
+    Exit,
+    // 00FE, back to 64x32
+    Lores,
+    // 00FF, 128x64
+    Hires,
+    // 0NNN, a call into the 1802's machine code, no interpreter does it
+    Sys(u16),
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipEqual { x: u8, nn: u8 },
+    // 4XNN
+    SkipNotEqual { x: u8, nn: u8 },
+    // 5XY0
+    SkipEqualReg { x: u8, y: u8 },
+    // 5XY2, VX to VY into memory at I, backwards when Y is smaller. I doesn't move, xo-chip
+    StoreRange { x: u8, y: u8 },
+    // 5XY3
+    LoadRange { x: u8, y: u8 },
+    // 6XNN
+    Set { x: u8, nn: u8 },
+    // 7XNN, VF stays as it is
+    Add { x: u8, nn: u8 },
+    // 8XY0
+    Copy { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4, VF is the carry
+    AddReg { x: u8, y: u8 },
+    // 8XY5, VX - VY, VF is 1 when it didn't borrow
+    Sub { x: u8, y: u8 },
+    // 8XY6
+    ShiftRight { x: u8, y: u8 },
+    // 8XY7, VY - VX
+    SubReverse { x: u8, y: u8 },
+    // 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    // 9XY0
+    SkipNotEqualReg { x: u8, y: u8 },
+    // ANNN
+    SetIndex(u16),
+    // F000 NNNN, the address is the next word, xo-chip
+    SetIndexLong,
+    // BNNN
+    JumpOffset(u16),
+    // CXNN
+    Random { x: u8, nn: u8 },
+    // DXYN, DXY0 is a 16x16 sprite on the schip
+    Draw { x: u8, y: u8, n: u8 },
+    // EX9E
+    SkipPressed { x: u8 },
+    // EXA1
+    SkipNotPressed { x: u8 },
+    // FN01, which of the two bit planes drawing and scrolling touch, xo-chip
+    Plane { n: u8 },
+    // F002, 16 bytes from I are the sound, xo-chip
+    Pattern,
+    // FX07
+    GetDelay { x: u8 },
+    // FX0A
+    WaitKey { x: u8 },
+    // FX15
+    SetDelay { x: u8 },
+    // FX18
+    SetSound { x: u8 },
+    // FX1E
+    AddIndex { x: u8 },
+    // FX29
+    Font { x: u8 },
+    // FX30, schip
+    BigFont { x: u8 },
+    // FX3A, how fast the pattern plays, xo-chip
+    Pitch { x: u8 },
+    // FX33
+    Bcd { x: u8 },
+    // FX55
+    Store { x: u8 },
+    // FX65
+    Load { x: u8 },
+    // FX75, the hp48's RPL user flags, schip
+    SaveFlags { x: u8 },
+    // FX85
+    LoadFlags { x: u8 },
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn decode(op: u16) -> Instruction {
+        let x = ((op >> 8) & 0xF) as u8;
+        let y = ((op >> 4) & 0xF) as u8;
+        let n = (op & 0xF) as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+
+        match (op >> 12, n) {
+            _ if op == 0x00E0 => Instruction::Clear,
+            _ if op == 0x00EE => Instruction::Return,
+            _ if op & 0xFFF0 == 0x00C0 => Instruction::ScrollDown { n },
+            _ if op & 0xFFF0 == 0x00D0 => Instruction::ScrollUp { n },
+            _ if op == 0x00FB => Instruction::ScrollRight,
+            _ if op == 0x00FC => Instruction::ScrollLeft,
+            _ if op == 0x00FD => Instruction::Exit,
+            _ if op == 0x00FE => Instruction::Lores,
+            _ if op == 0x00FF => Instruction::Hires,
+            (0x0, _) => Instruction::Sys(nnn),
+            (0x1, _) => Instruction::Jump(nnn),
+            (0x2, _) => Instruction::Call(nnn),
+            (0x3, _) => Instruction::SkipEqual { x, nn },
+            (0x4, _) => Instruction::SkipNotEqual { x, nn },
+            (0x5, 0x0) => Instruction::SkipEqualReg { x, y },
+            (0x5, 0x2) => Instruction::StoreRange { x, y },
+            (0x5, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _) => Instruction::Set { x, nn },
+            (0x7, _) => Instruction::Add { x, nn },
+            (0x8, 0x0) => Instruction::Copy { x, y },
+            (0x8, 0x1) => Instruction::Or { x, y },
+            (0x8, 0x2) => Instruction::And { x, y },
+            (0x8, 0x3) => Instruction::Xor { x, y },
+            (0x8, 0x4) => Instruction::AddReg { x, y },
+            (0x8, 0x5) => Instruction::Sub { x, y },
+            (0x8, 0x6) => Instruction::ShiftRight { x, y },
+            (0x8, 0x7) => Instruction::SubReverse { x, y },
+            (0x8, 0xE) => Instruction::ShiftLeft { x, y },
+            (0x9, 0x0) => Instruction::SkipNotEqualReg { x, y },
+            (0xA, _) => Instruction::SetIndex(nnn),
+            (0xB, _) => Instruction::JumpOffset(nnn),
+            (0xC, _) => Instruction::Random { x, nn },
+            (0xD, _) => Instruction::Draw { x, y, n },
+            (0xE, _) if nn == 0x9E => Instruction::SkipPressed { x },
+            (0xE, _) if nn == 0xA1 => Instruction::SkipNotPressed { x },
+            _ if op == 0xF000 => Instruction::SetIndexLong,
+            _ if op == 0xF002 => Instruction::Pattern,
+            (0xF, _) => match nn {
+                0x01 => Instruction::Plane { n: x },
+                0x07 => Instruction::GetDelay { x },
+                0x0A => Instruction::WaitKey { x },
+                0x15 => Instruction::SetDelay { x },
+                0x18 => Instruction::SetSound { x },
+                0x1E => Instruction::AddIndex { x },
+                0x29 => Instruction::Font { x },
+                0x30 => Instruction::BigFont { x },
+                0x3A => Instruction::Pitch { x },
+                0x33 => Instruction::Bcd { x },
+                0x55 => Instruction::Store { x },
+                0x65 => Instruction::Load { x },
+                0x75 => Instruction::SaveFlags { x },
+                0x85 => Instruction::LoadFlags { x },
+                _ => Instruction::Unknown(op),
+            },
+            _ => Instruction::Unknown(op),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown { n } => write!(f, "SCD {n:X}"),
+            Instruction::ScrollUp { n } => write!(f, "SCU {n:X}"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Sys(nnn) => write!(f, "SYS {nnn:03X}"),
+            Instruction::Jump(nnn) => write!(f, "JP {nnn:03X}"),
+            Instruction::Call(nnn) => write!(f, "CALL {nnn:03X}"),
+            Instruction::SkipEqual { x, nn } => write!(f, "SE V{x:X}, {nn:02X}"),
+            Instruction::SkipNotEqual { x, nn } => write!(f, "SNE V{x:X}, {nn:02X}"),
+            Instruction::SkipEqualReg { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            Instruction::StoreRange { x, y } => write!(f, "LD [I], V{x:X}-V{y:X}"),
+            Instruction::LoadRange { x, y } => write!(f, "LD V{x:X}-V{y:X}, [I]"),
+            Instruction::Set { x, nn } => write!(f, "LD V{x:X}, {nn:02X}"),
+            Instruction::Add { x, nn } => write!(f, "ADD V{x:X}, {nn:02X}"),
+            Instruction::Copy { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Instruction::Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            Instruction::And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Instruction::Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Instruction::Sub { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            Instruction::SubReverse { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            Instruction::SkipNotEqualReg { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            Instruction::SetIndex(nnn) => write!(f, "LD I, {nnn:03X}"),
+            // the address isn't part of the opcode, it's the word after it
+            Instruction::SetIndexLong => write!(f, "LD I, LONG"),
+            Instruction::JumpOffset(nnn) => write!(f, "JP V0, {nnn:03X}"),
+            Instruction::Random { x, nn } => write!(f, "RND V{x:X}, {nn:02X}"),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n:X}"),
+            Instruction::SkipPressed { x } => write!(f, "SKP V{x:X}"),
+            Instruction::SkipNotPressed { x } => write!(f, "SKNP V{x:X}"),
+            Instruction::Plane { n } => write!(f, "PLANE {n:X}"),
+            Instruction::Pattern => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{x:X}, DT"),
+            Instruction::WaitKey { x } => write!(f, "LD V{x:X}, K"),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{x:X}"),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{x:X}"),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{x:X}"),
+            Instruction::Font { x } => write!(f, "LD F, V{x:X}"),
+            Instruction::BigFont { x } => write!(f, "LD HF, V{x:X}"),
+            Instruction::Pitch { x } => write!(f, "PITCH V{x:X}"),
+            Instruction::Bcd { x } => write!(f, "LD B, V{x:X}"),
+            Instruction::Store { x } => write!(f, "LD [I], V{x:X}"),
+            Instruction::Load { x } => write!(f, "LD V{x:X}, [I]"),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{x:X}"),
+            Instruction::LoadFlags { x } => write!(f, "LD V{x:X}, R"),
+            // the raw word, like an assembler's data directive
+            Instruction::Unknown(op) => write!(f, "DW {op:04X}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_and_disassembles() {
+        assert_eq!(Instruction::decode(0x00E0), Instruction::Clear);
+        assert_eq!(Instruction::decode(0x1234), Instruction::Jump(0x234));
+        assert_eq!(
+            Instruction::decode(0x8AB6),
+            Instruction::ShiftRight { x: 0xA, y: 0xB }
+        );
+        assert_eq!(
+            Instruction::decode(0xD125),
+            Instruction::Draw { x: 1, y: 2, n: 5 }
+        );
+        assert_eq!(Instruction::decode(0xF365), Instruction::Load { x: 3 });
+        assert_eq!(
+            Instruction::decode(0x00C4),
+            Instruction::ScrollDown { n: 4 }
+        );
+        assert_eq!(Instruction::decode(0x00FF), Instruction::Hires);
+        assert_eq!(Instruction::decode(0xF275), Instruction::SaveFlags { x: 2 });
+        assert_eq!(
+            Instruction::decode(0x5122),
+            Instruction::StoreRange { x: 1, y: 2 }
+        );
+        assert_eq!(Instruction::decode(0xF000), Instruction::SetIndexLong);
+        assert_eq!(Instruction::decode(0xF201), Instruction::Plane { n: 2 });
+        assert_eq!(Instruction::decode(0xF53A), Instruction::Pitch { x: 5 });
+        assert_eq!(Instruction::decode(0x5121), Instruction::Unknown(0x5121));
+        assert_eq!(Instruction::decode(0xE1FF), Instruction::Unknown(0xE1FF));
+
+        let text: Vec<String> = [0x6A05, 0xA2F0, 0xD015, 0xF033, 0xFFFF]
+            .into_iter()
+            .map(|op| Instruction::decode(op).to_string())
+            .collect();
+        assert_eq!(
+            text,
+            [
+                "LD VA, 05",
+                "LD I, 2F0",
+                "DRW V0, V1, 5",
+                "LD B, V0",
+                "DW FFFF"
+            ]
+        );
+    }
+}
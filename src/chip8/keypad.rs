@@ -0,0 +1,81 @@
+// The 16 keys of the hex keypad, and FX0A waiting on them. The VIP's FX0A waited for a key to go
+// down and then back up, so a key that was already held when it started doesn't count, and the
+// game doesn't see the key until it's let go
+#[derive(Clone, Copy, Default)]
+pub struct Keypad {
+    held: [bool; 16],
+    wait: KeyWait,
+}
+
+#[derive(Clone, Copy, Default)]
+enum KeyWait {
+    // FX0A isn't running
+    #[default]
+    Idle,
+    Press,
+    Release(u8),
+    // let go of, FX0A picks it up next time it runs
+    Done(u8),
+}
+
+// the interpreter only ever looked at the low nibble, so V0 = 0x1A asks about key A
+fn index(key: u8) -> usize {
+    (key & 0xF) as usize
+}
+
+impl Keypad {
+    // once a frame, with every key as it is now. The edges move FX0A along
+    pub fn update(&mut self, keys: [bool; 16]) {
+        for (key, now) in keys.into_iter().enumerate() {
+            let went_down = now && !self.held[key];
+            let went_up = !now && self.held[key];
+            self.wait = match self.wait {
+                KeyWait::Press if went_down => KeyWait::Release(key as u8),
+                KeyWait::Release(waited) if waited as usize == key && went_up => {
+                    KeyWait::Done(waited)
+                }
+                wait => wait,
+            };
+            self.held[key] = now;
+        }
+    }
+
+    pub fn held(&self, key: u8) -> bool {
+        self.held[index(key)]
+    }
+
+    // FX0A, the key once one went down and back up since it first asked
+    pub fn wait(&mut self) -> Option<u8> {
+        match self.wait {
+            KeyWait::Done(key) => {
+                self.wait = KeyWait::Idle;
+                Some(key)
+            }
+            KeyWait::Idle => {
+                self.wait = KeyWait::Press;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // for save states, as a byte: the state in the high nibble and the key in the low one
+    pub fn wait_state(&self) -> u8 {
+        match self.wait {
+            KeyWait::Idle => 0x00,
+            KeyWait::Press => 0x10,
+            KeyWait::Release(key) => 0x20 | key,
+            KeyWait::Done(key) => 0x30 | key,
+        }
+    }
+
+    pub fn set_wait_state(&mut self, state: u8) {
+        let key = state & 0xF;
+        self.wait = match state >> 4 {
+            1 => KeyWait::Press,
+            2 => KeyWait::Release(key),
+            3 => KeyWait::Done(key),
+            _ => KeyWait::Idle,
+        };
+    }
+}
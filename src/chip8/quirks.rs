@@ -0,0 +1,120 @@
+// Chip8 was rewritten for every machine it ran on, and each rewrite changed a few instructions a
+// little. Roms written for one of them can break on the others, so these pick which one to act
+// like. The presets match what Timendus' quirks test rom expects of each
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Variant {
+    CosmacVip, // the original interpreter, 1977
+    Chip48,    // hp48 calculators
+    Schip,     // super-chip 1.1, also on the hp48
+    XoChip,    // octo's extension, what most new roms are written for
+}
+
+impl Chip8Variant {
+    pub fn from_name(name: &str) -> Option<Chip8Variant> {
+        match name.to_lowercase().as_str() {
+            "vip" | "chip8" => Some(Chip8Variant::CosmacVip),
+            "chip48" => Some(Chip8Variant::Chip48),
+            "schip" => Some(Chip8Variant::Schip),
+            "xochip" => Some(Chip8Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    // hires, scrolling, big sprites and fonts and the RPL flags
+    pub fn has_schip(self) -> bool {
+        matches!(self, Chip8Variant::Schip | Chip8Variant::XoChip)
+    }
+}
+
+// how much FX55 and FX65 move I after they're done
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    Unchanged,
+    // the chip48 got this one wrong
+    ByX,
+    ByXPlusOne,
+}
+
+impl IndexIncrement {
+    // after storing or loading V0 to VX
+    pub fn amount(self, x: u8) -> u16 {
+        match self {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => x as u16,
+            IndexIncrement::ByXPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chip8Quirks {
+    // 8XY6 and 8XYE shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    pub index_increment: IndexIncrement,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // BXNN jumps to XNN + VX, instead of BNNN jumping to NNN + V0
+    pub jump_uses_vx: bool,
+    // sprites get cut off at the edges of the screen instead of coming back on the other side
+    pub clip_sprites: bool,
+    // DXYN waits for the next frame, so there's a sprite a frame at most
+    pub display_wait: bool,
+    // in hires, DXYN sets VF to how many rows hit something or got cut off at the bottom, not 1
+    pub count_collision_rows: bool,
+    // how many calls deep the stack goes, the vip only had room for 12
+    pub stack_depth: usize,
+    // a call on a full stack or a return on an empty one moves around to the other end, instead of
+    // stopping the game. None of them did it, but some buggy roms get away with it
+    pub wrap_stack: bool,
+}
+
+impl Chip8Quirks {
+    pub fn for_variant(variant: Chip8Variant) -> Chip8Quirks {
+        match variant {
+            Chip8Variant::CosmacVip => Chip8Quirks {
+                shift_uses_vy: true,
+                index_increment: IndexIncrement::ByXPlusOne,
+                logic_resets_vf: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+                display_wait: true,
+                count_collision_rows: false,
+                stack_depth: 12,
+                wrap_stack: false,
+            },
+            Chip8Variant::Chip48 => Chip8Quirks {
+                shift_uses_vy: false,
+                index_increment: IndexIncrement::ByX,
+                logic_resets_vf: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                display_wait: false,
+                count_collision_rows: false,
+                stack_depth: 16,
+                wrap_stack: false,
+            },
+            Chip8Variant::Schip => Chip8Quirks {
+                shift_uses_vy: false,
+                index_increment: IndexIncrement::Unchanged,
+                logic_resets_vf: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                display_wait: false,
+                count_collision_rows: true,
+                stack_depth: 16,
+                wrap_stack: false,
+            },
+            Chip8Variant::XoChip => Chip8Quirks {
+                shift_uses_vy: true,
+                index_increment: IndexIncrement::ByXPlusOne,
+                logic_resets_vf: false,
+                jump_uses_vx: false,
+                clip_sprites: false,
+                display_wait: false,
+                count_collision_rows: false,
+                stack_depth: 16,
+                wrap_stack: false,
+            },
+        }
+    }
+}
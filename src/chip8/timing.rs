@@ -0,0 +1,69 @@
+// How long each instruction took on the COSMAC VIP, in the 1802's machine cycles (8 clocks each).
+// The interpreter there didn't run a fixed number of instructions a frame, it ran whatever fit
+// between the display interrupts, so old games were tuned to these. The costs are rounded from
+// Laurence Scotford's walk through the VIP interpreter's code
+use super::instruction::Instruction;
+
+// 1.7609 MHz / 8 clocks / 60 frames
+const MACHINE_CYCLES_PER_FRAME: i32 = 3668;
+// the display's DMA steals 1024 of them every frame, its interrupt routine most of the rest
+const DISPLAY_CYCLES: i32 = 1832;
+pub const CYCLES_PER_FRAME: i32 = MACHINE_CYCLES_PER_FRAME - DISPLAY_CYCLES;
+
+// fetching the two bytes, decoding and jumping to the routine, paid by every instruction
+const FETCH: u32 = 40;
+// the skips spend this much more when they do skip
+pub const SKIP_TAKEN: u32 = 4;
+
+// regs are the ones the instruction is about to run with, for the costs that depend on them
+pub fn vip_cycles(instruction: Instruction, regs: &[u8; 16]) -> u32 {
+    FETCH
+        + match instruction {
+            // 256 bytes of display memory, a byte at a time
+            Instruction::Clear => 3078,
+            Instruction::Return => 10,
+            Instruction::Jump(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SkipEqual { .. } | Instruction::SkipNotEqual { .. } => 10,
+            Instruction::SkipEqualReg { .. } | Instruction::SkipNotEqualReg { .. } => 14,
+            Instruction::Set { .. } => 6,
+            Instruction::Add { .. } => 10,
+            // they all go through the same patched-in 1802 instruction
+            Instruction::Copy { .. }
+            | Instruction::Or { .. }
+            | Instruction::And { .. }
+            | Instruction::Xor { .. }
+            | Instruction::AddReg { .. }
+            | Instruction::Sub { .. }
+            | Instruction::ShiftRight { .. }
+            | Instruction::SubReverse { .. }
+            | Instruction::ShiftLeft { .. } => 44,
+            Instruction::SetIndex(_) => 12,
+            Instruction::JumpOffset(_) => 22,
+            Instruction::Random { .. } => 36,
+            Instruction::Draw { x, n, .. } => draw_cycles(regs[x as usize], n),
+            Instruction::SkipPressed { .. } | Instruction::SkipNotPressed { .. } => 14,
+            Instruction::GetDelay { .. } => 10,
+            // a single look at the keypad, it runs again while nothing's pressed
+            Instruction::WaitKey { .. } => 18,
+            Instruction::SetDelay { .. } | Instruction::SetSound { .. } => 10,
+            Instruction::AddIndex { .. } => 16,
+            Instruction::Font { .. } => 20,
+            // counts each digit up by subtracting, so bigger digits take longer
+            Instruction::Bcd { x } => {
+                let value = regs[x as usize] as u32;
+                84 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+            }
+            Instruction::Store { x } | Instruction::Load { x } => 14 + 14 * (x as u32 + 1),
+            // the vip never had the rest, they stop the game anyway
+            _ => 0,
+        }
+}
+
+// every row is a byte xored into display memory, two when the sprite isn't on a byte boundary,
+// and shifting it there takes a few cycles a bit
+fn draw_cycles(x: u8, rows: u8) -> u32 {
+    let shift = (x % 8) as u32;
+    let row = if shift == 0 { 34 } else { 68 + 4 * shift };
+    26 + rows as u32 * row
+}
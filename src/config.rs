@@ -0,0 +1,99 @@
+// Settings that outlive a run, in play-us.cfg next to the states folder. It's a `key = value` a
+// line and # starts a comment, so it's easy to edit by hand. Saving only rewrites the lines that
+// changed, comments and all stay where they were
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const CONFIG_FILE: &str = "play-us.cfg";
+
+pub struct Config {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+// what a setting is called for a single rom, like chip8.hz.0123456789abcdef
+pub fn rom_key(key: &str, rom_hash: u64) -> String {
+    format!("{key}.{rom_hash:016x}")
+}
+
+impl Config {
+    // no file is just no settings yet
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Config> {
+        let path = path.into();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Config {
+            path,
+            lines: text.lines().map(str::to_string).collect(),
+        })
+    }
+
+    // the key and value of a line, None for blank ones and comments
+    fn entry(line: &str) -> Option<(&str, &str)> {
+        let line = line.split('#').next()?;
+        let (key, value) = line.split_once('=')?;
+        Some((key.trim(), value.trim()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        // the last one wins, like it would reading it top to bottom
+        self.lines
+            .iter()
+            .rev()
+            .filter_map(|line| Config::entry(line))
+            .find(|(at, _)| *at == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        let line = format!("{key} = {}", value.to_string());
+        let existing = self
+            .lines
+            .iter()
+            .rposition(|line| Config::entry(line).is_some_and(|(at, _)| at == key));
+        match existing {
+            Some(at) => self.lines[at] = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        fs::write(&self.path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments_when_saving() {
+        let path = std::env::temp_dir().join(format!("play-us-{}.cfg", std::process::id()));
+        fs::write(
+            &path,
+            "# how fast chip8 games run\nchip8.hz = 700 # a bit slow\n",
+        )
+        .unwrap();
+
+        let mut config = Config::load(&path).unwrap();
+        assert_eq!(config.get("chip8.hz"), Some("700"));
+        assert_eq!(config.get("chip8"), None);
+        config.set("chip8.hz", 900);
+        config.set(&rom_key("chip8.hz", 0xAB), 1200);
+        config.save().unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text,
+            "# how fast chip8 games run\nchip8.hz = 900\nchip8.hz.00000000000000ab = 1200\n"
+        );
+        assert!(Config::load(&path).unwrap().get("chip8.hz").is_none());
+    }
+}
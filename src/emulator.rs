@@ -1,3 +1,91 @@
-pub trait Emulator {
-    fn run(&mut self, rom: &[u8]);
-}
\ No newline at end of file
+// What every emulator core has to give the frontend. Cores don't know about windows, sound cards
+// or keyboards: the frontend runs them a frame at a time, shows the framebuffer, plays the samples
+// and hands the buttons back in
+use crate::error::EmuError;
+use crate::save_state::SaveState;
+
+// every core makes its sound at this rate, the frontend resamples it for speed changes
+pub const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const BLACK: Rgba = Rgba::new(0, 0, 0);
+    pub const WHITE: Rgba = Rgba::new(0xFF, 0xFF, 0xFF);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Rgba {
+        Rgba { r, g, b, a: 0xFF }
+    }
+}
+
+// the buttons of every machine, each core only looks at its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    // the chip8 hex keypad, 0 to F
+    Keypad(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl Button {
+    fn bit(self) -> u32 {
+        match self {
+            Button::Keypad(key) => 1 << (key & 0xF),
+            Button::Up => 1 << 16,
+            Button::Down => 1 << 17,
+            Button::Left => 1 << 18,
+            Button::Right => 1 << 19,
+            Button::A => 1 << 20,
+            Button::B => 1 << 21,
+            Button::Start => 1 << 22,
+            Button::Select => 1 << 23,
+        }
+    }
+}
+
+// which buttons are held down right now
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ButtonState(u32);
+
+impl ButtonState {
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.0 |= button.bit();
+        } else {
+            self.0 &= !button.bit();
+        }
+    }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        self.0 & button.bit() != 0
+    }
+}
+
+pub trait Emulator: SaveState {
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmuError>;
+    // like turning it off and on again, with the same rom in
+    fn reset(&mut self) -> Result<(), EmuError>;
+    // on an error the machine stays where it stopped, so it can be looked at
+    fn run_frame(&mut self) -> Result<(), EmuError>;
+    // pixels row by row, with the width and height. The size can change between frames
+    fn framebuffer(&self) -> (&[Rgba], usize, usize);
+    // the mono samples made since the last call, at SAMPLE_RATE
+    fn audio_samples(&mut self) -> Vec<f32>;
+    fn set_input(&mut self, buttons: ButtonState);
+    // frames a second at normal speed
+    fn frame_rate(&self) -> f64;
+    // the last instructions it ran, newest last, to show when something goes wrong
+    fn trace(&self) -> Vec<String>;
+}
@@ -0,0 +1,87 @@
+// What can go wrong running a game, in the cores and in the frontend. Bad opcodes and broken stacks
+// are the game's fault (or a missing feature), the rest is the rom file or the computer
+use std::fmt;
+use std::io;
+
+use crate::emulator::Emulator;
+
+#[derive(Debug)]
+pub enum EmuError {
+    UnknownOpcode { pc: u16, op: u16 },
+    // a call with the stack already full. calls are where the ones on it were made, oldest first
+    StackOverflow { pc: u16, calls: Vec<u16> },
+    // a return with nothing to return to
+    StackUnderflow { pc: u16 },
+    // sizes in bytes
+    RomTooLarge { size: usize, max: usize },
+    // the cartridge type byte from the header
+    UnsupportedCartridge(u8),
+    Io(io::Error),
+    Sdl(String),
+    // the egui debuggers couldn't open their window
+    Gui(String),
+}
+
+impl EmuError {
+    // whether it happened while running the game, so what it ran last is worth showing
+    pub fn in_game(&self) -> bool {
+        matches!(
+            self,
+            EmuError::UnknownOpcode { .. }
+                | EmuError::StackOverflow { .. }
+                | EmuError::StackUnderflow { .. }
+        )
+    }
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::UnknownOpcode { pc, op } => write!(f, "unknown opcode {op:04X} at {pc:04X}"),
+            EmuError::StackOverflow { pc, calls } => {
+                write!(f, "stack overflow at {pc:04X}, {} calls deep", calls.len())?;
+                if !calls.is_empty() {
+                    write!(f, ", called from")?;
+                    for call in calls {
+                        write!(f, " {call:04X}")?;
+                    }
+                }
+                Ok(())
+            }
+            EmuError::StackUnderflow { pc } => {
+                write!(f, "return with an empty stack at {pc:04X}")
+            }
+            EmuError::RomTooLarge { size, max } => {
+                write!(f, "rom is {size} bytes, it can't be more than {max}")
+            }
+            EmuError::UnsupportedCartridge(kind) => {
+                write!(f, "cartridge type {kind:02X} isn't supported yet")
+            }
+            EmuError::Io(err) => write!(f, "{err}"),
+            EmuError::Sdl(err) => write!(f, "sdl: {err}"),
+            EmuError::Gui(err) => write!(f, "debugger window: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
+
+impl From<io::Error> for EmuError {
+    fn from(err: io::Error) -> EmuError {
+        EmuError::Io(err)
+    }
+}
+
+// the error, and for the game's errors the instructions leading up to it
+pub fn report(err: &EmuError, emulator: &dyn Emulator) -> String {
+    let mut report = err.to_string();
+    let trace = emulator.trace();
+    if err.in_game() && !trace.is_empty() {
+        report.push_str("\n\nlast instructions, newest last:");
+        for line in trace {
+            report.push_str("\n  ");
+            report.push_str(&line);
+        }
+    }
+    report
+}
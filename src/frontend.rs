@@ -0,0 +1,296 @@
+// The sdl window every core runs in. It owns everything that isn't the machine itself: the
+// window, the sound, the keyboard, save state slots, rewind, speed and frame pacing
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::messagebox::{self, MessageBoxFlag};
+use sdl2::pixels::{Color, PixelFormatEnum};
+
+use crate::emulator::{self, Button, ButtonState, Emulator};
+use crate::error::{self, EmuError};
+use crate::rewind::{self, Rewind};
+use crate::scheduler::{AudioClock, FrameSync, Scheduler};
+use crate::speed::Speed;
+use crate::video::{self, Screen, StateHotkey};
+
+// the window is the biggest whole multiple of the framebuffer that fits in here
+const MAX_WINDOW: (u32, u32) = (1024, 768);
+// samples past this much get dropped, so the sound never lags far behind the game
+const MAX_QUEUED: usize = emulator::SAMPLE_RATE as usize / 10;
+
+// the square region from 1 to v on a qwerty keyboard
+pub const CHIP8_KEYS: [(Keycode, Button); 16] = [
+    (Keycode::NUM_1, Button::Keypad(1)),
+    (Keycode::NUM_2, Button::Keypad(2)),
+    (Keycode::NUM_3, Button::Keypad(3)),
+    (Keycode::NUM_4, Button::Keypad(12)),
+    (Keycode::Q, Button::Keypad(4)),
+    (Keycode::W, Button::Keypad(5)),
+    (Keycode::E, Button::Keypad(6)),
+    (Keycode::R, Button::Keypad(13)),
+    (Keycode::A, Button::Keypad(7)),
+    (Keycode::S, Button::Keypad(8)),
+    (Keycode::D, Button::Keypad(9)),
+    (Keycode::F, Button::Keypad(14)),
+    (Keycode::Z, Button::Keypad(10)),
+    (Keycode::X, Button::Keypad(0)),
+    (Keycode::C, Button::Keypad(11)),
+    (Keycode::V, Button::Keypad(15)),
+];
+
+pub const GAME_BOY_KEYS: [(Keycode, Button); 8] = [
+    (Keycode::Up, Button::Up),
+    (Keycode::Down, Button::Down),
+    (Keycode::Left, Button::Left),
+    (Keycode::Right, Button::Right),
+    (Keycode::X, Button::A),
+    (Keycode::Z, Button::B),
+    (Keycode::Return, Button::Start),
+    (Keycode::RShift, Button::Select),
+];
+
+pub struct Options {
+    // how far back rewind goes, 0 turns it off
+    pub rewind_seconds: u32,
+    // how many times faster fast-forward runs, 0 doesn't cap it
+    pub fast_forward: f64,
+    pub sync: FrameSync,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            rewind_seconds: rewind::DEFAULT_SECONDS,
+            fast_forward: 0.,
+            sync: FrameSync::Timer,
+        }
+    }
+}
+
+// plays whatever the frontend queued, silence when it runs out
+struct Speaker {
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    clock: AudioClock,
+}
+
+impl AudioCallback for Speaker {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [Self::Channel]) {
+        self.clock.advance(out.len());
+        let mut queue = self.queue.lock().unwrap();
+        for sample in out.iter_mut() {
+            *sample = queue.pop_front().unwrap_or(0.);
+        }
+    }
+}
+
+// like playing a tape faster or slower: twice the speed keeps every other sample, so it plays in
+// half the time and an octave up
+fn resample(samples: &[f32], rate: f32) -> impl Iterator<Item = f32> + '_ {
+    let count = (samples.len() as f32 / rate) as usize;
+    (0..count).map(move |at| samples[((at as f32 * rate) as usize).min(samples.len() - 1)])
+}
+
+// the speed keys that toggle, see video.rs
+fn speed_hotkey(speed: &mut Speed, keycode: Keycode) {
+    if keycode == video::SLOW_MOTION_KEY {
+        speed.toggle_slow_motion();
+    }
+    if keycode == video::PAUSE_KEY {
+        speed.toggle_pause();
+    }
+    if keycode == video::FRAME_ADVANCE_KEY {
+        speed.advance();
+    }
+}
+
+// runs until the window closes. Errors get printed and shown in a message box before they're
+// returned, with what the game ran last when it was the game's fault
+pub fn run(
+    emulator: &mut dyn Emulator,
+    keys: &[(Keycode, Button)],
+    options: &Options,
+) -> Result<(), EmuError> {
+    let result = run_loop(emulator, keys, options);
+    if let Err(err) = &result {
+        let report = error::report(err, emulator);
+        println!("{report}");
+        // without sdl there's nothing to show it in, it's printed anyway
+        let _ =
+            messagebox::show_simple_message_box(MessageBoxFlag::ERROR, "play-us", &report, None);
+    }
+    result
+}
+
+fn run_loop(
+    emulator: &mut dyn Emulator,
+    keys: &[(Keycode, Button)],
+    options: &Options,
+) -> Result<(), EmuError> {
+    let (_, width, height) = emulator.framebuffer();
+    let scale = (MAX_WINDOW.0 / width as u32)
+        .min(MAX_WINDOW.1 / height as u32)
+        .max(1);
+    let mut screen = Screen::new(
+        Some(width as u32 * scale),
+        Some(height as u32 * scale),
+        options.sync == FrameSync::Vsync,
+    )?;
+    let texture_creator = screen.canvas.texture_creator();
+    let mut texture_size = (width, height);
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+        .map_err(|err| EmuError::Sdl(err.to_string()))?;
+
+    let queue = Arc::new(Mutex::new(VecDeque::new()));
+    let audio_clock = AudioClock::default();
+    let audio_spec = AudioSpecDesired {
+        freq: Some(emulator::SAMPLE_RATE as i32),
+        channels: Some(1), // mono channel
+        samples: None,
+    };
+    let audio_device = screen
+        .audio
+        .open_playback(None, &audio_spec, |_spec| Speaker {
+            queue: queue.clone(),
+            clock: audio_clock.clone(),
+        })
+        .map_err(EmuError::Sdl)?;
+    // never paused, so the audio clock keeps going even when the game is quiet
+    audio_device.resume();
+
+    let mut scheduler = Scheduler::new(options.sync);
+    scheduler.audio_clock(audio_clock, audio_device.spec().freq);
+    let mut speed = Speed::new(emulator.frame_rate());
+    speed.set_fast_forward(options.fast_forward);
+    let mut rewind = Rewind::new(options.rewind_seconds);
+    let mut rewinding = false;
+    let mut buttons = ButtonState::default();
+    emulator.read_flags_file();
+    let mut flags = emulator.flags();
+
+    'main_loop: loop {
+        for event in screen.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'main_loop Ok(()),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat,
+                    ..
+                } => {
+                    if !repeat {
+                        speed_hotkey(&mut speed, keycode);
+                    }
+                    if keycode == video::FAST_FORWARD_KEY {
+                        speed.set_fast_forwarding(true);
+                    }
+                    if keycode == video::REWIND_KEY {
+                        rewinding = true;
+                    }
+                    if keycode == video::RESET_KEY && !repeat {
+                        emulator.reset()?;
+                    }
+                    match video::state_hotkey(keycode, keymod) {
+                        Some(StateHotkey::Save(slot)) => emulator.save_slot(slot),
+                        Some(StateHotkey::Load(slot)) => emulator.load_slot(slot),
+                        None => {}
+                    }
+                    for (key, button) in keys {
+                        if *key == keycode {
+                            buttons.set(*button, true);
+                        }
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if keycode == video::FAST_FORWARD_KEY {
+                        speed.set_fast_forwarding(false);
+                    }
+                    if keycode == video::REWIND_KEY {
+                        rewinding = false;
+                    }
+                    for (key, button) in keys {
+                        if *key == keycode {
+                            buttons.set(*button, false);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        emulator.set_input(buttons);
+
+        if rewinding {
+            // one saved frame back every frame, so it rewinds in real time
+            if let Some(state) = rewind.pop() {
+                if let Err(err) = emulator.load_state(&state) {
+                    println!("couldn't rewind: {err}");
+                }
+            }
+        } else if speed.run_frame() {
+            rewind.push(emulator.save_state());
+            emulator.run_frame()?;
+            if emulator.flags() != flags {
+                flags = emulator.flags();
+                emulator.write_flags_file();
+            }
+        }
+
+        let samples = emulator.audio_samples();
+        if let Some(rate) = speed.audio_rate().filter(|_| !rewinding) {
+            let mut queue = queue.lock().unwrap();
+            queue.extend(resample(&samples, rate));
+            let extra = queue.len().saturating_sub(MAX_QUEUED);
+            queue.drain(..extra);
+        }
+
+        let (pixels, width, height) = emulator.framebuffer();
+        if texture_size != (width, height) {
+            texture_size = (width, height);
+            texture = texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+                .map_err(|err| EmuError::Sdl(err.to_string()))?;
+        }
+        texture
+            .with_lock(None, |buffer, pitch| {
+                for (y, row) in pixels.chunks(width).enumerate() {
+                    for (x, pixel) in row.iter().enumerate() {
+                        let at = y * pitch + x * 4;
+                        buffer[at..at + 4].copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+                    }
+                }
+            })
+            .map_err(EmuError::Sdl)?;
+        screen.canvas.set_draw_color(Color::BLACK);
+        screen.canvas.clear();
+        // stretched over the whole window, whatever size the framebuffer is
+        screen
+            .canvas
+            .copy(&texture, None, None)
+            .map_err(EmuError::Sdl)?;
+        screen.canvas.present();
+
+        scheduler.wait(speed.frame_time());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resamples_like_a_tape() {
+        let samples: Vec<f32> = (0..8).map(|at| at as f32).collect();
+        assert_eq!(resample(&samples, 2.).collect::<Vec<_>>(), [0., 2., 4., 6.]);
+        assert_eq!(resample(&samples, 0.5).count(), 16);
+        assert_eq!(resample(&samples, 1.).collect::<Vec<_>>(), samples);
+        assert_eq!(resample(&[], 0.25).count(), 0);
+    }
+}
@@ -0,0 +1,1040 @@
+// RegA and friends read better than REG_A all over the opcode table
+#![allow(non_upper_case_globals)]
+
+use super::model::Quirks;
+use super::ppu::OamCorruption;
+use crate::save_state::{Chunk, ChunkReader, StateError};
+
+// Everything the cpu touches goes through here, so the cpu doesn't care if it's talking to the real
+// game boy memory map or to a bunch of test vectors
+pub trait Bus {
+    // read and write take one M-cycle each (4 clock cycles)
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    // an M-cycle where the cpu is busy doing something internal and the bus is idle
+    fn tick(&mut self);
+
+    // side effect free access, for the cpu's interrupt checks and for debugging tools
+    fn peek(&self, address: u16) -> u8;
+    fn poke(&mut self, address: u16, value: u8);
+
+    // the 16 bit inc/dec unit puts its value on the address bus, on DMG that messes with the OAM
+    // if it points there during mode 2
+    fn oam_bug(&mut self, _address: u16, _kind: OamCorruption) {}
+}
+
+pub type Regs = usize;
+pub const RegA: usize = 0;
+pub const RegF: usize = 1;
+pub const RegB: usize = 2;
+pub const RegC: usize = 3;
+pub const RegD: usize = 4;
+pub const RegE: usize = 5;
+pub const RegH: usize = 6;
+pub const RegL: usize = 7;
+
+// order the opcodes use to encode 8 bit operands, index 6 is (HL) and never hits the array
+const R8_ORDER: [Regs; 8] = [RegB, RegC, RegD, RegE, RegH, RegL, usize::MAX, RegA];
+const R8_HL: u8 = 6;
+
+pub const IF_ADDRESS: u16 = 0xFF0F;
+pub const IE_ADDRESS: u16 = 0xFFFF;
+
+pub struct Cpu {
+    pub regs: [u8; 8],
+    // AF, BC, DE, HL, by the gods what does it mean why this order
+    pub pc: u16,
+    pub sp: u16, // stack pointer
+    pub ime: bool,
+    pub ime_delay: bool, // EI only enables interrupts after the next instruction
+    pub halted: bool,
+    // the byte after a buggy HALT gets fetched without moving pc
+    halt_bug: bool,
+    pub quirks: Quirks,
+    // clock cycles ran since power on
+    pub cycles: u64,
+    // the illegal opcode it locked up on, pc stays on it
+    pub locked: Option<u8>,
+}
+
+impl Cpu {
+    pub fn new() -> Cpu {
+        Cpu {
+            regs: [
+                0x01, // A
+                0xB0, // F
+                0x00, // B
+                0x13, // C
+                0x00, // D
+                0xD8, // E
+                0x01, // H
+                0x4D, // L
+            ],
+            pc: 0x100,
+            sp: 0xFFFE,
+            ime: false,
+            ime_delay: false,
+            halted: false,
+            halt_bug: false,
+            quirks: Quirks::default(),
+            cycles: 0,
+            locked: None,
+        }
+    }
+
+    // quirks are settings, not state, so they stay out of save states
+    pub fn save(&self, chunk: &mut Chunk) {
+        chunk.bytes(&self.regs);
+        chunk.u16(self.pc);
+        chunk.u16(self.sp);
+        chunk.bool(self.ime);
+        chunk.bool(self.ime_delay);
+        chunk.bool(self.halted);
+        chunk.bool(self.halt_bug);
+        chunk.u64(self.cycles);
+    }
+
+    pub fn load(&mut self, chunk: &mut ChunkReader) -> Result<(), StateError> {
+        chunk.bytes_into(&mut self.regs)?;
+        self.pc = chunk.u16()?;
+        self.sp = chunk.u16()?;
+        self.ime = chunk.bool()?;
+        self.ime_delay = chunk.bool()?;
+        self.halted = chunk.bool()?;
+        self.halt_bug = chunk.bool()?;
+        self.cycles = chunk.u64()?;
+        Ok(())
+    }
+
+    // bus helpers, they keep the cycle count honest
+    #[inline]
+    fn read<B: Bus>(&mut self, bus: &mut B, address: u16) -> u8 {
+        self.cycles += 4;
+        bus.read(address)
+    }
+    #[inline]
+    fn write<B: Bus>(&mut self, bus: &mut B, address: u16, value: u8) {
+        self.cycles += 4;
+        bus.write(address, value)
+    }
+    #[inline]
+    fn tick<B: Bus>(&mut self, bus: &mut B) {
+        self.cycles += 4;
+        bus.tick()
+    }
+    #[inline]
+    fn fetch<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let value = self.read(bus, self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+    #[inline]
+    fn fetch_16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        // little endian, low byte comes first
+        let low = self.fetch(bus) as u16;
+        let high = self.fetch(bus) as u16;
+        (high << 8) | low
+    }
+
+    fn push<B: Bus>(&mut self, bus: &mut B, value: u16) {
+        // apparently the stack is "upside down"
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(bus, self.sp, (value >> 8) as u8);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(bus, self.sp, (value & 0x00FF) as u8);
+    }
+    fn pop<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let low = self.read(bus, self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+        let high = self.read(bus, self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+        (high << 8) | low
+    }
+    // same thing, but POP is one of the instructions the OAM bug cares about
+    fn pop_oam_bug<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        bus.oam_bug(self.sp, OamCorruption::ReadIncrease);
+        let low = self.read(bus, self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+        bus.oam_bug(self.sp, OamCorruption::ReadIncrease);
+        let high = self.read(bus, self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+        (high << 8) | low
+    }
+
+    #[inline]
+    pub fn get_pair(&self, left: Regs, right: Regs) -> u16 {
+        ((self.regs[left] as u16) << 8) | self.regs[right] as u16
+    }
+    #[inline]
+    pub fn set_pair(&mut self, left: Regs, right: Regs, value: u16) {
+        self.regs[left] = (value >> 8) as u8;
+        self.regs[right] = (value & 0x00FF) as u8;
+        if right == RegF {
+            // the low nibble of F doesn't exist
+            self.regs[RegF] &= 0xF0;
+        }
+    }
+    #[inline]
+    pub fn get_hl(&self) -> u16 {
+        self.get_pair(RegH, RegL)
+    }
+
+    // pairs as encoded in opcodes: BC, DE, HL, SP
+    fn get_rr(&self, index: u8) -> u16 {
+        match index {
+            0 => self.get_pair(RegB, RegC),
+            1 => self.get_pair(RegD, RegE),
+            2 => self.get_pair(RegH, RegL),
+            _ => self.sp,
+        }
+    }
+    fn set_rr(&mut self, index: u8, value: u16) {
+        match index {
+            0 => self.set_pair(RegB, RegC, value),
+            1 => self.set_pair(RegD, RegE, value),
+            2 => self.set_pair(RegH, RegL, value),
+            _ => self.sp = value,
+        }
+    }
+
+    fn read_r8<B: Bus>(&mut self, bus: &mut B, index: u8) -> u8 {
+        if index == R8_HL {
+            self.read(bus, self.get_hl())
+        } else {
+            self.regs[R8_ORDER[index as usize]]
+        }
+    }
+    fn write_r8<B: Bus>(&mut self, bus: &mut B, index: u8, value: u8) {
+        if index == R8_HL {
+            self.write(bus, self.get_hl(), value)
+        } else {
+            self.regs[R8_ORDER[index as usize]] = value
+        }
+    }
+
+    #[inline]
+    fn set_flag(&mut self, bit: u8, state: bool) {
+        match state {
+            true => self.regs[RegF] |= 1 << bit,
+            false => self.regs[RegF] &= !(1 << bit),
+        }
+    }
+    #[inline]
+    pub fn set_z_flag(&mut self, state: bool) {
+        self.set_flag(7, state)
+    }
+    #[inline]
+    pub fn set_n_flag(&mut self, state: bool) {
+        self.set_flag(6, state)
+    }
+    #[inline]
+    pub fn set_h_flag(&mut self, state: bool) {
+        self.set_flag(5, state)
+    }
+    #[inline]
+    pub fn set_c_flag(&mut self, state: bool) {
+        self.set_flag(4, state)
+    }
+    #[inline]
+    pub fn get_z_flag(&self) -> bool {
+        self.regs[RegF] & (1 << 7) != 0
+    }
+    #[inline]
+    pub fn get_n_flag(&self) -> bool {
+        self.regs[RegF] & (1 << 6) != 0
+    }
+    #[inline]
+    pub fn get_h_flag(&self) -> bool {
+        self.regs[RegF] & (1 << 5) != 0
+    }
+    #[inline]
+    pub fn get_c_flag(&self) -> bool {
+        self.regs[RegF] & (1 << 4) != 0
+    }
+
+    // NZ, Z, NC, C
+    fn condition(&self, index: u8) -> bool {
+        match index {
+            0 => !self.get_z_flag(),
+            1 => self.get_z_flag(),
+            2 => !self.get_c_flag(),
+            _ => self.get_c_flag(),
+        }
+    }
+
+    // Runs one instruction (or one halted M-cycle, or an interrupt dispatch)
+    // returns the clock cycles it took
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u64 {
+        let start = self.cycles;
+
+        // https://gbdev.io/pandocs/Interrupts.html#ffff--ie-interrupt-enable
+        let interrupts = bus.peek(IE_ADDRESS) & bus.peek(IF_ADDRESS) & 0x1F;
+        // nothing but a reset gets it out of here
+        if self.locked.is_some() {
+            self.tick(bus);
+            return self.cycles - start;
+        }
+        if self.halted {
+            if interrupts == 0 {
+                self.tick(bus);
+                return self.cycles - start;
+            }
+            // any pending interrupt wakes the cpu up, even with IME off
+            self.halted = false;
+        }
+        if self.ime && interrupts != 0 {
+            self.dispatch_interrupt(bus, interrupts);
+            return self.cycles - start;
+        }
+
+        let enable_ime = self.ime_delay;
+        self.compute(bus);
+        if enable_ime && self.ime_delay {
+            self.ime = true;
+            self.ime_delay = false;
+        }
+
+        self.cycles - start
+    }
+
+    fn dispatch_interrupt<B: Bus>(&mut self, bus: &mut B, interrupts: u8) {
+        // lowest bit has priority: vblank, stat, timer, serial, joypad
+        let bit = interrupts.trailing_zeros() as u16;
+        self.ime = false;
+        self.ime_delay = false;
+        self.tick(bus);
+        self.tick(bus);
+        self.push(bus, self.pc);
+        let flags = bus.peek(IF_ADDRESS);
+        bus.poke(IF_ADDRESS, flags & !(1 << bit));
+        self.pc = 0x40 + bit * 8;
+        self.tick(bus);
+    }
+
+    // full op functions
+    fn op_alu(&mut self, operation: u8, value: u8) {
+        let a = self.regs[RegA];
+        let carry = self.get_c_flag() as u8;
+        let result = match operation {
+            // ADD
+            0 => {
+                self.set_h_flag((a & 0xF) + (value & 0xF) > 0xF);
+                self.set_c_flag(a as u16 + value as u16 > 0xFF);
+                a.wrapping_add(value)
+            }
+            // ADC
+            1 => {
+                self.set_h_flag((a & 0xF) + (value & 0xF) + carry > 0xF);
+                self.set_c_flag(a as u16 + value as u16 + carry as u16 > 0xFF);
+                a.wrapping_add(value).wrapping_add(carry)
+            }
+            // SUB and CP
+            2 | 7 => {
+                self.set_h_flag(a & 0xF < value & 0xF);
+                self.set_c_flag(value > a);
+                a.wrapping_sub(value)
+            }
+            // SBC
+            3 => {
+                self.set_h_flag(a & 0xF < (value & 0xF) + carry);
+                self.set_c_flag((a as u16) < value as u16 + carry as u16);
+                a.wrapping_sub(value).wrapping_sub(carry)
+            }
+            // AND
+            4 => {
+                self.set_h_flag(true);
+                self.set_c_flag(false);
+                a & value
+            }
+            // XOR
+            5 => {
+                self.set_h_flag(false);
+                self.set_c_flag(false);
+                a ^ value
+            }
+            // OR
+            _ => {
+                self.set_h_flag(false);
+                self.set_c_flag(false);
+                a | value
+            }
+        };
+        self.set_z_flag(result == 0);
+        self.set_n_flag(matches!(operation, 2 | 3 | 7));
+        if operation != 7 {
+            self.regs[RegA] = result;
+        }
+    }
+
+    fn op_inc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.set_z_flag(result == 0);
+        self.set_n_flag(false);
+        self.set_h_flag(value & 0x0F == 0x0F);
+        result
+    }
+    fn op_dec(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.set_z_flag(result == 0);
+        self.set_n_flag(true);
+        self.set_h_flag(value & 0x0F == 0);
+        result
+    }
+
+    fn op_add_hl<B: Bus>(&mut self, bus: &mut B, value: u16) {
+        let hl = self.get_hl();
+        // aparently the half flag takes in bit 11 and 12 as half in 16 bit math
+        self.set_h_flag((hl & 0xFFF) + (value & 0xFFF) > 0xFFF);
+        self.set_c_flag(hl as u32 + value as u32 > 0xFFFF);
+        self.set_n_flag(false);
+        self.set_pair(RegH, RegL, hl.wrapping_add(value));
+        self.tick(bus);
+    }
+
+    // SP + signed 8 bit, shared by ADD SP,r8 and LD HL,SP+r8
+    fn op_sp_offset(&mut self, offset: u8) -> u16 {
+        let sp = self.sp;
+        // flags come from the unsigned low byte math
+        self.set_z_flag(false);
+        self.set_n_flag(false);
+        self.set_h_flag((sp & 0xF) + (offset as u16 & 0xF) > 0xF);
+        self.set_c_flag((sp & 0xFF) + offset as u16 > 0xFF);
+        sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn op_daa(&mut self) {
+        let mut a = self.regs[RegA];
+        let mut carry = self.get_c_flag();
+        if !self.get_n_flag() {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if self.get_h_flag() || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        } else {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if self.get_h_flag() {
+                a = a.wrapping_sub(0x06);
+            }
+        }
+        self.regs[RegA] = a;
+        self.set_z_flag(a == 0);
+        self.set_h_flag(false);
+        self.set_c_flag(carry);
+    }
+
+    // rotates and shifts from the CB table, index is bits 3-5 of the opcode
+    fn op_rotate(&mut self, operation: u8, value: u8) -> u8 {
+        let carry_in = self.get_c_flag() as u8;
+        let (result, carry_out) = match operation {
+            // RLC, RRC, RL, RR, SLA, SRA, SWAP, SRL
+            0 => (value.rotate_left(1), value & 0x80 != 0),
+            1 => (value.rotate_right(1), value & 1 != 0),
+            2 => ((value << 1) | carry_in, value & 0x80 != 0),
+            3 => ((value >> 1) | (carry_in << 7), value & 1 != 0),
+            4 => (value << 1, value & 0x80 != 0),
+            5 => ((value >> 1) | (value & 0x80), value & 1 != 0),
+            6 => (value.rotate_left(4), false),
+            _ => (value >> 1, value & 1 != 0),
+        };
+        self.set_z_flag(result == 0);
+        self.set_n_flag(false);
+        self.set_h_flag(false);
+        self.set_c_flag(carry_out);
+        result
+    }
+
+    fn op_jr<B: Bus>(&mut self, bus: &mut B, condition: bool) {
+        let offset = self.fetch(bus) as i8;
+        if condition {
+            self.pc = self.pc.wrapping_add(offset as u16);
+            self.tick(bus);
+        }
+    }
+    fn op_jp<B: Bus>(&mut self, bus: &mut B, condition: bool) {
+        let address = self.fetch_16(bus);
+        if condition {
+            self.pc = address;
+            self.tick(bus);
+        }
+    }
+    fn op_call<B: Bus>(&mut self, bus: &mut B, condition: bool) {
+        let address = self.fetch_16(bus);
+        if condition {
+            self.tick(bus);
+            self.push(bus, self.pc);
+            self.pc = address;
+        }
+    }
+    fn op_ret<B: Bus>(&mut self, bus: &mut B) {
+        self.pc = self.pop(bus);
+        self.tick(bus);
+    }
+    fn op_rst<B: Bus>(&mut self, bus: &mut B, vector: u16) {
+        self.tick(bus);
+        self.push(bus, self.pc);
+        self.pc = vector;
+    }
+
+    // I know I probably shouldn't start directly implement opcodes, but preguicinha of doing
+    // the game boy architecture and stuff
+    fn compute<B: Bus>(&mut self, bus: &mut B) {
+        let opcode = match self.halt_bug {
+            true => {
+                self.halt_bug = false;
+                self.read(bus, self.pc)
+            }
+            false => self.fetch(bus),
+        };
+
+        // most of the table is regular, bits 3-5 are the destination/operation and 0-2 the source
+        let dst = (opcode >> 3) & 0b111;
+        let src = opcode & 0b111;
+        let pair = (opcode >> 4) & 0b11;
+
+        match opcode {
+            0x00 => {}
+            // LD rr,d16
+            0x01 | 0x11 | 0x21 | 0x31 => {
+                let value = self.fetch_16(bus);
+                self.set_rr(pair, value);
+            }
+            // LD (BC),A / LD (DE),A
+            0x02 | 0x12 => self.write(bus, self.get_rr(pair), self.regs[RegA]),
+            // LD (HL+),A / LD (HL-),A
+            0x22 | 0x32 => {
+                let hl = self.get_hl();
+                self.write(bus, hl, self.regs[RegA]);
+                let hl = if opcode == 0x22 {
+                    hl.wrapping_add(1)
+                } else {
+                    hl.wrapping_sub(1)
+                };
+                self.set_pair(RegH, RegL, hl);
+            }
+            // INC rr
+            0x03 | 0x13 | 0x23 | 0x33 => {
+                let value = self.get_rr(pair);
+                bus.oam_bug(value, OamCorruption::Write);
+                self.set_rr(pair, value.wrapping_add(1));
+                self.tick(bus);
+            }
+            // DEC rr
+            0x0B | 0x1B | 0x2B | 0x3B => {
+                let value = self.get_rr(pair);
+                bus.oam_bug(value, OamCorruption::Write);
+                self.set_rr(pair, value.wrapping_sub(1));
+                self.tick(bus);
+            }
+            // INC r / INC (HL)
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => {
+                let value = self.read_r8(bus, dst);
+                let result = self.op_inc(value);
+                self.write_r8(bus, dst, result);
+            }
+            // DEC r / DEC (HL)
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => {
+                let value = self.read_r8(bus, dst);
+                let result = self.op_dec(value);
+                self.write_r8(bus, dst, result);
+            }
+            // LD r,d8 / LD (HL),d8
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+                let value = self.fetch(bus);
+                self.write_r8(bus, dst, value);
+            }
+            // RLCA, RRCA, RLA, RRA: same as the CB ones but Z is always cleared
+            0x07 | 0x0F | 0x17 | 0x1F => {
+                self.regs[RegA] = self.op_rotate(dst, self.regs[RegA]);
+                self.set_z_flag(false);
+            }
+            0x08 => {
+                let address = self.fetch_16(bus);
+                self.write(bus, address, (self.sp & 0x00FF) as u8);
+                self.write(bus, address.wrapping_add(1), (self.sp >> 8) as u8);
+            }
+            // ADD HL,rr
+            0x09 | 0x19 | 0x29 | 0x39 => self.op_add_hl(bus, self.get_rr(pair)),
+            // LD A,(BC) / LD A,(DE)
+            0x0A | 0x1A => self.regs[RegA] = self.read(bus, self.get_rr(pair)),
+            // LD A,(HL+) / LD A,(HL-)
+            0x2A | 0x3A => {
+                // NOTE: ADDRESSING MEMORY WITH HL DOESN'T TREAT IT LIKE LITTLE ENDIAN
+                let hl = self.get_hl();
+                self.regs[RegA] = self.read(bus, hl);
+                let hl = if opcode == 0x2A {
+                    hl.wrapping_add(1)
+                } else {
+                    hl.wrapping_sub(1)
+                };
+                self.set_pair(RegH, RegL, hl);
+            }
+            0x10 => {
+                // STOP is followed by a byte that gets skipped, there is no speed switch or
+                // low power mode here, so this is just a weird NOP
+                self.pc = self.pc.wrapping_add(1);
+            }
+            0x18 => self.op_jr(bus, true),
+            // JR cc,r8
+            0x20 | 0x28 | 0x30 | 0x38 => self.op_jr(bus, self.condition(dst - 4)),
+            0x27 => self.op_daa(),
+            0x2F => {
+                self.regs[RegA] = !self.regs[RegA];
+                self.set_n_flag(true);
+                self.set_h_flag(true);
+            }
+            0x37 => {
+                self.set_n_flag(false);
+                self.set_h_flag(false);
+                self.set_c_flag(true);
+            }
+            0x3F => {
+                self.set_n_flag(false);
+                self.set_h_flag(false);
+                self.set_c_flag(!self.get_c_flag());
+            }
+            0x76 => {
+                let pending = bus.peek(IE_ADDRESS) & bus.peek(IF_ADDRESS) & 0x1F != 0;
+                if !self.ime && pending {
+                    // doesn't halt at all, and on the real thing it also trips over the next byte
+                    self.halt_bug = self.quirks.halt_bug;
+                } else {
+                    self.halted = true;
+                }
+            }
+            // LD r,r' and friends with (HL)
+            0x40..=0x7F => {
+                let value = self.read_r8(bus, src);
+                self.write_r8(bus, dst, value);
+            }
+            // ADD, ADC, SUB, SBC, AND, XOR, OR, CP with a register or (HL)
+            0x80..=0xBF => {
+                let value = self.read_r8(bus, src);
+                self.op_alu(dst, value);
+            }
+            // RET cc
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => {
+                self.tick(bus);
+                if self.condition(dst) {
+                    self.op_ret(bus);
+                }
+            }
+            // POP rr, AF takes the place of SP here
+            0xC1 | 0xD1 | 0xE1 | 0xF1 => {
+                let value = self.pop_oam_bug(bus);
+                match pair {
+                    3 => self.set_pair(RegA, RegF, value),
+                    _ => self.set_rr(pair, value),
+                }
+            }
+            // JP cc,a16
+            0xC2 | 0xCA | 0xD2 | 0xDA => self.op_jp(bus, self.condition(dst)),
+            0xC3 => self.op_jp(bus, true),
+            // CALL cc,a16
+            0xC4 | 0xCC | 0xD4 | 0xDC => self.op_call(bus, self.condition(dst)),
+            // PUSH rr
+            0xC5 | 0xD5 | 0xE5 | 0xF5 => {
+                let value = match pair {
+                    3 => self.get_pair(RegA, RegF),
+                    _ => self.get_rr(pair),
+                };
+                self.tick(bus);
+                self.push(bus, value);
+            }
+            // ALU d8
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+                let value = self.fetch(bus);
+                self.op_alu(dst, value);
+            }
+            // RST n
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                self.op_rst(bus, (dst as u16) * 8)
+            }
+            0xC9 => self.op_ret(bus),
+            0xCB => self.compute_cb(bus),
+            0xCD => self.op_call(bus, true),
+            0xD9 => {
+                // unlike EI, RETI enables interrupts right away
+                self.ime = true;
+                self.op_ret(bus);
+            }
+            0xE0 => {
+                let address = 0xFF00 | self.fetch(bus) as u16;
+                self.write(bus, address, self.regs[RegA]);
+            }
+            0xF0 => {
+                let address = 0xFF00 | self.fetch(bus) as u16;
+                // Turns out the game keeps waiting for the game to draw, which is when 0xFF44 (the y lcd counter)
+                // Is 148 (or whatever it is in hex)
+                self.regs[RegA] = self.read(bus, address);
+            }
+            0xE2 => self.write(bus, 0xFF00 | self.regs[RegC] as u16, self.regs[RegA]),
+            0xF2 => self.regs[RegA] = self.read(bus, 0xFF00 | self.regs[RegC] as u16),
+            0xE8 => {
+                let offset = self.fetch(bus);
+                self.sp = self.op_sp_offset(offset);
+                self.tick(bus);
+                self.tick(bus);
+            }
+            0xF8 => {
+                let offset = self.fetch(bus);
+                let value = self.op_sp_offset(offset);
+                self.set_pair(RegH, RegL, value);
+                self.tick(bus);
+            }
+            0xE9 => self.pc = self.get_hl(),
+            0xF9 => {
+                self.sp = self.get_hl();
+                self.tick(bus);
+            }
+            0xEA => {
+                let address = self.fetch_16(bus);
+                self.write(bus, address, self.regs[RegA]);
+            }
+            0xFA => {
+                let address = self.fetch_16(bus);
+                self.regs[RegA] = self.read(bus, address);
+            }
+            0xF3 => {
+                self.ime = false;
+                self.ime_delay = false;
+            }
+            0xFB => {
+                self.ime_delay = true;
+            }
+            // D3, DB, DD, E3, E4, EB, EC, ED, F4, FC and FD don't exist, the real thing just locks up
+            _ => {
+                self.pc = self.pc.wrapping_sub(1);
+                self.locked = Some(opcode);
+            }
+        }
+    }
+
+    fn compute_cb<B: Bus>(&mut self, bus: &mut B) {
+        // there's a second one :)
+        let opcode = self.fetch(bus);
+        let bit = (opcode >> 3) & 0b111;
+        let target = opcode & 0b111;
+        let value = self.read_r8(bus, target);
+
+        match opcode {
+            // RLC, RRC, RL, RR, SLA, SRA, SWAP, SRL
+            0x00..=0x3F => {
+                let result = self.op_rotate(bit, value);
+                self.write_r8(bus, target, result);
+            }
+            // BIT n,r
+            0x40..=0x7F => {
+                self.set_z_flag(value & (1 << bit) == 0);
+                self.set_n_flag(false);
+                self.set_h_flag(true);
+            }
+            // RES n,r
+            0x80..=0xBF => self.write_r8(bus, target, value & !(1 << bit)),
+            // SET n,r
+            _ => self.write_r8(bus, target, value | (1 << bit)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Runner for the SingleStepTests/sm83 json vectors (https://github.com/SingleStepTests/sm83)
+    // The full suite is too big to keep in the repo, point SM83_TESTS at a checkout of its `v1`
+    // folder to run it, otherwise only the handwritten vectors below run
+    use super::*;
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq)]
+    enum Cycle {
+        Read(u16, u8),
+        Write(u16, u8),
+        Idle,
+    }
+
+    struct TestBus {
+        ram: HashMap<u16, u8>,
+        cycles: Vec<Cycle>,
+    }
+
+    impl Bus for TestBus {
+        fn read(&mut self, address: u16) -> u8 {
+            let value = self.peek(address);
+            self.cycles.push(Cycle::Read(address, value));
+            value
+        }
+        fn write(&mut self, address: u16, value: u8) {
+            self.cycles.push(Cycle::Write(address, value));
+            self.poke(address, value);
+        }
+        fn tick(&mut self) {
+            self.cycles.push(Cycle::Idle);
+        }
+        fn peek(&self, address: u16) -> u8 {
+            *self.ram.get(&address).unwrap_or(&0)
+        }
+        fn poke(&mut self, address: u16, value: u8) {
+            self.ram.insert(address, value);
+        }
+    }
+
+    const REG_NAMES: [(&str, Regs); 8] = [
+        ("a", RegA),
+        ("f", RegF),
+        ("b", RegB),
+        ("c", RegC),
+        ("d", RegD),
+        ("e", RegE),
+        ("h", RegH),
+        ("l", RegL),
+    ];
+
+    fn field(state: &Value, name: &str) -> u16 {
+        state[name].as_u64().unwrap_or(0) as u16
+    }
+
+    fn setup(state: &Value) -> (Cpu, TestBus) {
+        let mut cpu = Cpu::new();
+        for (name, reg) in REG_NAMES {
+            cpu.regs[reg] = field(state, name) as u8;
+        }
+        cpu.pc = field(state, "pc");
+        cpu.sp = field(state, "sp");
+        cpu.ime = field(state, "ime") != 0;
+
+        let mut bus = TestBus {
+            ram: HashMap::new(),
+            cycles: vec![],
+        };
+        if let Some(ie) = state.get("ie") {
+            bus.poke(IE_ADDRESS, ie.as_u64().unwrap() as u8);
+        }
+        for entry in state["ram"].as_array().unwrap() {
+            bus.poke(
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            );
+        }
+        (cpu, bus)
+    }
+
+    fn expected_cycles(test: &Value) -> Vec<Cycle> {
+        test["cycles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|cycle| {
+                // idle cycles are either null or have no data on the bus
+                let (Some(address), Some(value), Some(pins)) =
+                    (cycle[0].as_u64(), cycle[1].as_u64(), cycle[2].as_str())
+                else {
+                    return Cycle::Idle;
+                };
+                match pins.as_bytes() {
+                    [b'r', ..] => Cycle::Read(address as u16, value as u8),
+                    [_, b'w', ..] => Cycle::Write(address as u16, value as u8),
+                    _ => Cycle::Idle,
+                }
+            })
+            .collect()
+    }
+
+    fn run_vector(test: &Value) -> Result<(), String> {
+        let (mut cpu, mut bus) = setup(&test["initial"]);
+        cpu.step(&mut bus);
+
+        let expected = &test["final"];
+        let mut errors = vec![];
+        for (name, reg) in REG_NAMES {
+            if cpu.regs[reg] as u16 != field(expected, name) {
+                errors.push(format!(
+                    "{name}: got {:02X} expected {:02X}",
+                    cpu.regs[reg],
+                    field(expected, name)
+                ));
+            }
+        }
+        if cpu.pc != field(expected, "pc") {
+            errors.push(format!(
+                "pc: got {:04X} expected {:04X}",
+                cpu.pc,
+                field(expected, "pc")
+            ));
+        }
+        if cpu.sp != field(expected, "sp") {
+            errors.push(format!(
+                "sp: got {:04X} expected {:04X}",
+                cpu.sp,
+                field(expected, "sp")
+            ));
+        }
+        // some versions of the suite split the EI delay out into its own field
+        let ime = match expected.get("ei") {
+            Some(_) => cpu.ime,
+            None => cpu.ime || cpu.ime_delay,
+        };
+        if ime != (field(expected, "ime") != 0) {
+            errors.push(format!("ime: got {ime}"));
+        }
+        for entry in expected["ram"].as_array().unwrap() {
+            let address = entry[0].as_u64().unwrap() as u16;
+            let value = entry[1].as_u64().unwrap() as u8;
+            if bus.peek(address) != value {
+                errors.push(format!(
+                    "ram {address:04X}: got {:02X} expected {value:02X}",
+                    bus.peek(address)
+                ));
+            }
+        }
+        let cycles = expected_cycles(test);
+        if bus.cycles != cycles {
+            errors.push(format!(
+                "cycles: got {:?} expected {:?}",
+                bus.cycles, cycles
+            ));
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(format!("{}: {}", test["name"], errors.join(", "))),
+        }
+    }
+
+    fn run_vectors(tests: &Value) -> Vec<String> {
+        tests
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|test| run_vector(test).err())
+            .collect()
+    }
+
+    #[test]
+    fn handwritten_vectors() {
+        let tests: Value = serde_json::from_str(HANDWRITTEN).unwrap();
+        let failures = run_vectors(&tests);
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn halt_bug_reads_next_byte_twice() {
+        let (mut cpu, mut bus) = setup(&serde_json::json!({
+            "pc": 256, "sp": 65534, "a": 0, "ie": 1,
+            "ram": [[256, 0x76], [257, 0x3C], [0xFF0F, 1]]
+        }));
+        cpu.quirks.halt_bug = true;
+        for _ in 0..3 {
+            cpu.step(&mut bus);
+        }
+        // HALT, INC A without moving pc, then INC A again
+        assert!(!cpu.halted);
+        assert_eq!(cpu.regs[RegA], 2);
+        assert_eq!(cpu.pc, 258);
+    }
+
+    #[test]
+    fn sm83_vectors() {
+        let Ok(folder) = std::env::var("SM83_TESTS") else {
+            eprintln!("SM83_TESTS not set, skipping the full sm83 suite");
+            return;
+        };
+        let mut files: Vec<_> = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+
+        let mut failures = vec![];
+        for file in files {
+            let tests: Value =
+                serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+            // one failure per opcode is enough to know where to look
+            if let Some(failure) = run_vectors(&tests).into_iter().next() {
+                failures.push(failure);
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    // same format as the real suite
+    const HANDWRITTEN: &str = r#"[
+        {
+            "name": "00 nop",
+            "initial": {"pc": 49152, "sp": 65534, "a": 1, "b": 0, "c": 19, "d": 0, "e": 216, "f": 176, "h": 1, "l": 77, "ime": 0, "ram": [[49152, 0]]},
+            "final": {"pc": 49153, "sp": 65534, "a": 1, "b": 0, "c": 19, "d": 0, "e": 216, "f": 176, "h": 1, "l": 77, "ime": 0, "ram": [[49152, 0]]},
+            "cycles": [[49152, 0, "r-m"]]
+        },
+        {
+            "name": "01 ld bc,d16",
+            "initial": {"pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 1], [257, 52], [258, 18]]},
+            "final": {"pc": 259, "sp": 65534, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 1], [257, 52], [258, 18]]},
+            "cycles": [[256, 1, "r-m"], [257, 52, "r-m"], [258, 18, "r-m"]]
+        },
+        {
+            "name": "c5 push bc",
+            "initial": {"pc": 256, "sp": 53248, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 197]]},
+            "final": {"pc": 257, "sp": 53246, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 197], [53247, 18], [53246, 52]]},
+            "cycles": [[256, 197, "r-m"], null, [53247, 18, "-wm"], [53246, 52, "-wm"]]
+        },
+        {
+            "name": "f1 pop af masks f",
+            "initial": {"pc": 256, "sp": 53246, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 241], [53246, 255], [53247, 18]]},
+            "final": {"pc": 257, "sp": 53248, "a": 18, "b": 0, "c": 0, "d": 0, "e": 0, "f": 240, "h": 0, "l": 0, "ime": 0, "ram": [[256, 241], [53246, 255], [53247, 18]]},
+            "cycles": [[256, 241, "r-m"], [53246, 255, "r-m"], [53247, 18, "r-m"]]
+        },
+        {
+            "name": "cd call a16",
+            "initial": {"pc": 256, "sp": 53248, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 205], [257, 0], [258, 64]]},
+            "final": {"pc": 16384, "sp": 53246, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[53247, 1], [53246, 3]]},
+            "cycles": [[256, 205, "r-m"], [257, 0, "r-m"], [258, 64, "r-m"], null, [53247, 1, "-wm"], [53246, 3, "-wm"]]
+        },
+        {
+            "name": "20 jr nz not taken",
+            "initial": {"pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 128, "h": 0, "l": 0, "ime": 0, "ram": [[256, 32], [257, 254]]},
+            "final": {"pc": 258, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 128, "h": 0, "l": 0, "ime": 0, "ram": []},
+            "cycles": [[256, 32, "r-m"], [257, 254, "r-m"]]
+        },
+        {
+            "name": "18 jr backwards",
+            "initial": {"pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 24], [257, 254]]},
+            "final": {"pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": []},
+            "cycles": [[256, 24, "r-m"], [257, 254, "r-m"], null]
+        },
+        {
+            "name": "27 daa after add",
+            "initial": {"pc": 256, "sp": 65534, "a": 60, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 39]]},
+            "final": {"pc": 257, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": []},
+            "cycles": [[256, 39, "r-m"]]
+        },
+        {
+            "name": "9f sbc a,a with carry",
+            "initial": {"pc": 256, "sp": 65534, "a": 5, "b": 0, "c": 0, "d": 0, "e": 0, "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[256, 159]]},
+            "final": {"pc": 257, "sp": 65534, "a": 255, "b": 0, "c": 0, "d": 0, "e": 0, "f": 112, "h": 0, "l": 0, "ime": 0, "ram": []},
+            "cycles": [[256, 159, "r-m"]]
+        },
+        {
+            "name": "f8 ld hl,sp-1",
+            "initial": {"pc": 256, "sp": 65535, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 248], [257, 255]]},
+            "final": {"pc": 258, "sp": 65535, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 48, "h": 255, "l": 254, "ime": 0, "ram": []},
+            "cycles": [[256, 248, "r-m"], [257, 255, "r-m"], null]
+        },
+        {
+            "name": "cb 7e bit 7,(hl)",
+            "initial": {"pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 16, "h": 192, "l": 0, "ime": 0, "ram": [[256, 203], [257, 126], [49152, 127]]},
+            "final": {"pc": 258, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 176, "h": 192, "l": 0, "ime": 0, "ram": []},
+            "cycles": [[256, 203, "r-m"], [257, 126, "r-m"], [49152, 127, "r-m"]]
+        },
+        {
+            "name": "cb 36 swap (hl)",
+            "initial": {"pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 192, "l": 0, "ime": 0, "ram": [[256, 203], [257, 54], [49152, 18]]},
+            "final": {"pc": 258, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 192, "l": 0, "ime": 0, "ram": [[49152, 33]]},
+            "cycles": [[256, 203, "r-m"], [257, 54, "r-m"], [49152, 18, "r-m"], [49152, 33, "-wm"]]
+        }
+    ]"#;
+}
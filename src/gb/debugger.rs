@@ -0,0 +1,364 @@
+// The debugger side of things that doesn't care about how it's shown: breakpoints, watchpoints
+// and stepping. The egui window lives in debugger_ui.rs
+use super::disasm::{self, Instruction};
+use super::{GameBoyEmulator, FRAME_CYCLES};
+
+// clock cycles in a scanline, so stepping doesn't hang with the lcd off
+const LINE_CYCLES: u64 = 456;
+
+// the names from pandocs, so watchpoints can be set on LY instead of FF44
+pub const IO_REGISTERS: &[(&str, u16)] = &[
+    ("P1", 0xFF00),
+    ("SB", 0xFF01),
+    ("SC", 0xFF02),
+    ("DIV", 0xFF04),
+    ("TIMA", 0xFF05),
+    ("TMA", 0xFF06),
+    ("TAC", 0xFF07),
+    ("IF", 0xFF0F),
+    ("NR10", 0xFF10),
+    ("NR11", 0xFF11),
+    ("NR12", 0xFF12),
+    ("NR13", 0xFF13),
+    ("NR14", 0xFF14),
+    ("NR50", 0xFF24),
+    ("NR51", 0xFF25),
+    ("NR52", 0xFF26),
+    ("LCDC", 0xFF40),
+    ("STAT", 0xFF41),
+    ("SCY", 0xFF42),
+    ("SCX", 0xFF43),
+    ("LY", 0xFF44),
+    ("LYC", 0xFF45),
+    ("DMA", 0xFF46),
+    ("BGP", 0xFF47),
+    ("OBP0", 0xFF48),
+    ("OBP1", 0xFF49),
+    ("WY", 0xFF4A),
+    ("WX", 0xFF4B),
+    ("IE", 0xFFFF),
+];
+
+pub fn io_register_name(address: u16) -> Option<&'static str> {
+    IO_REGISTERS
+        .iter()
+        .find(|(_, at)| *at == address)
+        .map(|(name, _)| *name)
+}
+
+// hex, with or without $ or 0x in front, or the name of an io register
+pub fn parse_address(text: &str) -> Option<u16> {
+    let text = text.trim();
+    if let Some((_, address)) = IO_REGISTERS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(text))
+    {
+        return Some(*address);
+    }
+    let hex = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+// a line of the disassembly, ran is for the ones above pc that already ran
+pub struct DisassemblyLine {
+    pub bank: u8,
+    pub instruction: Instruction,
+    pub ran: bool,
+}
+
+// going backwards in z80 like code is guesswork, so above pc is the last few that ran
+pub fn disassembly(gb: &GameBoyEmulator, ran: usize, ahead: usize) -> Vec<DisassemblyLine> {
+    let line = |address: u16, ran: bool| {
+        let bank = gb.mmu.rom_bank(address);
+        DisassemblyLine {
+            bank,
+            instruction: disasm::decode_at(&gb.mmu, address, bank, gb.symbols.as_ref()),
+            ran,
+        }
+    };
+    let skip = gb.history.len().saturating_sub(ran);
+    let mut lines: Vec<DisassemblyLine> = gb
+        .history
+        .iter()
+        .skip(skip)
+        .map(|address| line(*address, true))
+        .collect();
+    let mut address = gb.cpu.pc;
+    for _ in 0..ahead {
+        let next = line(address, false);
+        address = address.wrapping_add(next.instruction.size());
+        lines.push(next);
+    }
+    lines
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    // only stops when this rom bank is mapped, any bank when None
+    pub bank: Option<u8>,
+    pub address: u16,
+}
+
+impl Breakpoint {
+    // `4000` or `03:4000`
+    pub fn parse(text: &str) -> Option<Breakpoint> {
+        match text.trim().split_once(':') {
+            Some((bank, address)) => Some(Breakpoint {
+                bank: Some(u8::from_str_radix(bank.trim(), 16).ok()?),
+                address: parse_address(address)?,
+            }),
+            None => Some(Breakpoint {
+                bank: None,
+                address: parse_address(text)?,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access, // either
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &Access) -> bool {
+        access.address == self.address
+            && match self.kind {
+                WatchKind::Read => !access.write,
+                WatchKind::Write => access.write,
+                WatchKind::Access => true,
+            }
+    }
+}
+
+// a read or write the cpu did on the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(Breakpoint),
+    Watchpoint(Access),
+    // ran into an illegal opcode, it won't go anywhere until a reset
+    Locked(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Instruction,
+    Scanline,
+    Frame,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub paused: bool,
+    // why it stopped the last time, if it was a breakpoint or a watchpoint
+    pub stop_reason: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn toggle_breakpoint(&mut self, breakpoint: Breakpoint) {
+        match self.breakpoints.iter().position(|at| *at == breakpoint) {
+            Some(index) => {
+                self.breakpoints.remove(index);
+            }
+            None => self.breakpoints.push(breakpoint),
+        }
+    }
+
+    pub fn breakpoint_at(&self, gb: &GameBoyEmulator, address: u16) -> Option<Breakpoint> {
+        let bank = gb.mmu.rom_bank(address);
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| {
+                breakpoint.address == address && breakpoint.bank.is_none_or(|at| at == bank)
+            })
+            .copied()
+    }
+
+    // Runs until the step is done or something made it stop. Resuming from a breakpoint runs the
+    // instruction it stopped on, so it doesn't stop at the same place again
+    pub fn run(&mut self, gb: &mut GameBoyEmulator, step: Step) -> Option<StopReason> {
+        let start_cycles = gb.cpu.cycles;
+        let start_line = gb.mmu.ppu.lines;
+        let start_frame = gb.mmu.ppu.frames;
+        let watching = !self.watchpoints.is_empty();
+        if watching {
+            gb.mmu.access_log = Some(vec![]);
+        }
+
+        let mut resuming = matches!(
+            self.stop_reason.take(),
+            Some(StopReason::Breakpoint(breakpoint)) if breakpoint.address == gb.cpu.pc
+        );
+        let reason = loop {
+            if !resuming {
+                if let Some(breakpoint) = self.breakpoint_at(gb, gb.cpu.pc) {
+                    break Some(StopReason::Breakpoint(breakpoint));
+                }
+            }
+            resuming = false;
+
+            gb.step();
+            if let Some(op) = gb.cpu.locked {
+                break Some(StopReason::Locked(op));
+            }
+
+            if let Some(log) = &mut gb.mmu.access_log {
+                let hit = log.iter().find(|access| {
+                    self.watchpoints
+                        .iter()
+                        .any(|watchpoint| watchpoint.matches(access))
+                });
+                if let Some(access) = hit {
+                    break Some(StopReason::Watchpoint(*access));
+                }
+                log.clear();
+            }
+
+            let ran = gb.cpu.cycles - start_cycles;
+            let done = match step {
+                Step::Instruction => true,
+                Step::Scanline => gb.mmu.ppu.lines != start_line || ran >= LINE_CYCLES,
+                Step::Frame => gb.mmu.ppu.frames != start_frame || ran >= FRAME_CYCLES,
+            };
+            if done {
+                break None;
+            }
+        };
+
+        gb.mmu.access_log = None;
+        if reason.is_some() {
+            self.paused = true;
+            self.stop_reason = reason;
+        }
+        reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::gb::GbModel;
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(parse_address("ly"), Some(0xFF44));
+        assert_eq!(parse_address("$C000"), Some(0xC000));
+        assert_eq!(
+            Breakpoint::parse("03:4000"),
+            Some(Breakpoint {
+                bank: Some(3),
+                address: 0x4000
+            })
+        );
+        assert_eq!(Breakpoint::parse("zz"), None);
+    }
+
+    #[test]
+    fn stops_on_breakpoints_and_watchpoints() {
+        // ld a, $42; ld [$C000], a; jr -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x107].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+        let mut gb = GameBoyEmulator::with_model(GbModel::Dmg, false);
+        gb.load_rom(&rom).unwrap();
+
+        let mut debugger = Debugger::new();
+        debugger
+            .breakpoints
+            .push(Breakpoint::parse("0102").unwrap());
+        let reason = debugger.run(&mut gb, Step::Frame);
+        assert!(matches!(reason, Some(StopReason::Breakpoint(_))));
+        assert_eq!(gb.cpu.pc, 0x102);
+
+        debugger.breakpoints.clear();
+        debugger.watchpoints.push(Watchpoint {
+            address: 0xC000,
+            kind: WatchKind::Write,
+        });
+        let reason = debugger.run(&mut gb, Step::Frame);
+        assert_eq!(
+            reason,
+            Some(StopReason::Watchpoint(Access {
+                address: 0xC000,
+                value: 0x42,
+                write: true
+            }))
+        );
+        assert!(debugger.paused);
+    }
+
+    #[test]
+    fn stops_on_the_first_instruction_of_a_frame() {
+        // nop; jr -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x103].copy_from_slice(&[0x00, 0x18, 0xFE]);
+        let mut gb = GameBoyEmulator::with_model(GbModel::Dmg, false);
+        gb.load_rom(&rom).unwrap();
+
+        let mut debugger = Debugger::new();
+        debugger
+            .breakpoints
+            .push(Breakpoint::parse("0100").unwrap());
+        let reason = debugger.run(&mut gb, Step::Frame);
+        assert!(matches!(reason, Some(StopReason::Breakpoint(_))));
+        assert_eq!(gb.cpu.pc, 0x100);
+
+        // resuming runs past it, and the loop never comes back
+        assert_eq!(debugger.run(&mut gb, Step::Frame), None);
+        assert_eq!(gb.cpu.pc, 0x101);
+        assert_eq!(debugger.stop_reason, None);
+    }
+
+    #[test]
+    fn disassembles_around_pc() {
+        // push bc; pop de; jr -4
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0xC5, 0xD1, 0x18, 0xFC]);
+        let mut gb = GameBoyEmulator::with_model(GbModel::Dmg, false);
+        gb.load_rom(&rom).unwrap();
+
+        let mut debugger = Debugger::new();
+        for _ in 0..3 {
+            debugger.run(&mut gb, Step::Instruction);
+        }
+        let lines = disassembly(&gb, 2, 3);
+        let text: Vec<(&str, bool)> = lines
+            .iter()
+            .map(|line| (line.instruction.text.as_str(), line.ran))
+            .collect();
+        assert_eq!(
+            text,
+            [
+                ("pop de", true),
+                ("jr $0100", true),
+                ("push bc", false),
+                ("pop de", false),
+                ("jr $0100", false)
+            ]
+        );
+    }
+}
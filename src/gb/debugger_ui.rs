@@ -0,0 +1,354 @@
+// The debugger window. The emulator runs inside the egui update loop, one frame per repaint,
+// so everything shown here is live instead of a snapshot
+use eframe::egui::{self, Color32, RichText};
+
+use super::cpu::{Bus, RegA, RegB, RegC, RegD, RegE, RegF, RegH, RegL};
+use super::debugger::{
+    disassembly, io_register_name, parse_address, Breakpoint, Debugger, Step, StopReason,
+    WatchKind, Watchpoint,
+};
+use super::disasm;
+use super::vram_viewer::VramViewer;
+use super::GameBoyEmulator;
+use crate::error::EmuError;
+use crate::memory_viewer::MemoryViewer;
+
+const DISASM_LINES: usize = 20;
+const HISTORY_LINES: usize = 6;
+const STACK_WORDS: u16 = 8;
+
+struct DebuggerApp {
+    emulator: GameBoyEmulator,
+    debugger: Debugger,
+    memory_viewer: MemoryViewer,
+    show_memory: bool,
+    vram_viewer: VramViewer,
+    show_tiles: bool,
+    show_tilemaps: bool,
+    show_oam: bool,
+    breakpoint_text: String,
+    watchpoint_text: String,
+    watchpoint_kind: WatchKind,
+    error: Option<String>,
+}
+
+pub fn run(emulator: GameBoyEmulator) -> Result<(), EmuError> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([900.0, 720.0]),
+        ..Default::default()
+    };
+    let app = DebuggerApp {
+        emulator,
+        debugger: Debugger::new(),
+        memory_viewer: MemoryViewer::new(),
+        show_memory: true,
+        vram_viewer: VramViewer::new(),
+        show_tiles: false,
+        show_tilemaps: false,
+        show_oam: false,
+        breakpoint_text: String::new(),
+        watchpoint_text: String::new(),
+        watchpoint_kind: WatchKind::Write,
+        error: None,
+    };
+    eframe::run_native(
+        "play-us debugger",
+        options,
+        Box::new(|_cc| Ok(Box::new(app))),
+    )
+    .map_err(|err| EmuError::Gui(err.to_string()))
+}
+
+fn mono(text: impl Into<String>) -> RichText {
+    RichText::new(text).monospace().size(14.0)
+}
+
+impl DebuggerApp {
+    fn step(&mut self, step: Step) {
+        self.memory_viewer.snapshot(&self.emulator.mmu);
+        self.debugger.run(&mut self.emulator, step);
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let label = if self.debugger.paused {
+                "Resume"
+            } else {
+                "Pause"
+            };
+            if ui.button(label).clicked() {
+                self.debugger.paused = !self.debugger.paused;
+            }
+            ui.add_enabled_ui(self.debugger.paused, |ui| {
+                for (label, step) in [
+                    ("Step", Step::Instruction),
+                    ("Step line", Step::Scanline),
+                    ("Step frame", Step::Frame),
+                ] {
+                    if ui.button(label).clicked() {
+                        self.step(step);
+                    }
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_memory, "Memory");
+            ui.checkbox(&mut self.show_tiles, "Tiles");
+            ui.checkbox(&mut self.show_tilemaps, "Tilemaps");
+            ui.checkbox(&mut self.show_oam, "OAM");
+        });
+
+        let status = match self.debugger.stop_reason {
+            Some(StopReason::Breakpoint(breakpoint)) => {
+                format!("Breakpoint at {:04X}", breakpoint.address)
+            }
+            Some(StopReason::Watchpoint(access)) => format!(
+                "Watchpoint: {} {:02X} at {:04X}{}",
+                if access.write { "wrote" } else { "read" },
+                access.value,
+                access.address,
+                io_register_name(access.address)
+                    .map(|name| format!(" ({name})"))
+                    .unwrap_or_default()
+            ),
+            Some(StopReason::Locked(op)) => format!(
+                "Locked up on illegal opcode {op:02X} at {:04X}",
+                self.emulator.cpu.pc
+            ),
+            None if self.debugger.paused => "Paused".to_string(),
+            None => "Running".to_string(),
+        };
+        ui.label(status);
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let cpu = &self.emulator.cpu;
+        egui::Grid::new("registers").show(ui, |ui| {
+            for (name, left, right) in [
+                ("AF", RegA, RegF),
+                ("BC", RegB, RegC),
+                ("DE", RegD, RegE),
+                ("HL", RegH, RegL),
+            ] {
+                ui.label(mono(name));
+                ui.label(mono(format!("{:04X}", cpu.get_pair(left, right))));
+                ui.end_row();
+            }
+            ui.label(mono("SP"));
+            ui.label(mono(format!("{:04X}", cpu.sp)));
+            ui.end_row();
+            ui.label(mono("PC"));
+            ui.label(mono(format!(
+                "{:02X}:{:04X}",
+                self.emulator.mmu.rom_bank(cpu.pc),
+                cpu.pc
+            )));
+            ui.end_row();
+        });
+
+        let flag = |set: bool, name: &'static str| if set { name } else { "-" };
+        ui.label(mono(format!(
+            "Flags {} {} {} {}",
+            flag(cpu.get_z_flag(), "Z"),
+            flag(cpu.get_n_flag(), "N"),
+            flag(cpu.get_h_flag(), "H"),
+            flag(cpu.get_c_flag(), "C"),
+        )));
+        ui.label(mono(format!(
+            "IME {}  IE {:02X}  IF {:02X}{}",
+            cpu.ime as u8,
+            self.emulator.mmu.peek(0xFFFF),
+            self.emulator.mmu.peek(0xFF0F),
+            if cpu.halted { "  HALT" } else { "" }
+        )));
+        ui.label(mono(format!(
+            "LY {:3}  mode {}",
+            self.emulator.mmu.peek(0xFF44),
+            self.emulator.mmu.ppu.mode
+        )));
+    }
+
+    fn stack(&self, ui: &mut egui::Ui) {
+        let sp = self.emulator.cpu.sp;
+        for index in 0..STACK_WORDS {
+            let address = sp.wrapping_add(index * 2);
+            let value = self.emulator.mmu.peek(address) as u16
+                | (self.emulator.mmu.peek(address.wrapping_add(1)) as u16) << 8;
+            ui.label(mono(format!("{address:04X}: {value:04X}")));
+        }
+    }
+
+    fn disassembly(&mut self, ui: &mut egui::Ui) {
+        let symbols = self.emulator.symbols.as_ref();
+        let pc = self.emulator.cpu.pc;
+
+        let mut toggled = None;
+        for line in disassembly(&self.emulator, HISTORY_LINES, DISASM_LINES) {
+            let (bank, ran) = (line.bank, line.ran);
+            let address = line.instruction.address;
+            let instruction = line.instruction;
+            let has_breakpoint = self
+                .debugger
+                .breakpoint_at(&self.emulator, address)
+                .is_some();
+            ui.horizontal(|ui| {
+                let marker = if has_breakpoint { "●" } else { " " };
+                if ui
+                    .add(
+                        egui::Label::new(mono(marker).color(Color32::RED))
+                            .sense(egui::Sense::click()),
+                    )
+                    .on_hover_text("toggle breakpoint")
+                    .clicked()
+                {
+                    toggled = Some(address);
+                }
+                let color = if !ran && address == pc {
+                    Color32::YELLOW
+                } else if ran {
+                    Color32::GRAY
+                } else {
+                    Color32::WHITE
+                };
+                ui.label(mono(disasm::format_line(&instruction, bank, symbols)).color(color));
+            });
+        }
+        if let Some(address) = toggled {
+            self.debugger.toggle_breakpoint(Breakpoint {
+                bank: None,
+                address,
+            });
+        }
+    }
+
+    fn breakpoints(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.breakpoint_text).desired_width(80.0))
+                .on_hover_text("4000 or 03:4000 for a specific bank");
+            if ui.button("Add").clicked() {
+                match Breakpoint::parse(&self.breakpoint_text) {
+                    Some(breakpoint) => {
+                        self.debugger.breakpoints.push(breakpoint);
+                        self.breakpoint_text.clear();
+                        self.error = None;
+                    }
+                    None => {
+                        self.error = Some(format!("{} is not an address", self.breakpoint_text))
+                    }
+                }
+            }
+        });
+        let mut removed = None;
+        for (index, breakpoint) in self.debugger.breakpoints.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let bank = match breakpoint.bank {
+                    Some(bank) => format!("{bank:02X}:"),
+                    None => "**:".to_string(),
+                };
+                ui.label(mono(format!("{bank}{:04X}", breakpoint.address)));
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+        if let Some(index) = removed {
+            self.debugger.breakpoints.remove(index);
+        }
+    }
+
+    fn watchpoints(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.watchpoint_text).desired_width(80.0))
+                .on_hover_text("C000, or an io register like LY");
+            egui::ComboBox::from_id_source("watch kind")
+                .selected_text(format!("{:?}", self.watchpoint_kind))
+                .show_ui(ui, |ui| {
+                    for kind in [WatchKind::Read, WatchKind::Write, WatchKind::Access] {
+                        ui.selectable_value(&mut self.watchpoint_kind, kind, format!("{kind:?}"));
+                    }
+                });
+            if ui.button("Add").clicked() {
+                match parse_address(&self.watchpoint_text) {
+                    Some(address) => {
+                        self.debugger.watchpoints.push(Watchpoint {
+                            address,
+                            kind: self.watchpoint_kind,
+                        });
+                        self.watchpoint_text.clear();
+                        self.error = None;
+                    }
+                    None => {
+                        self.error = Some(format!("{} is not an address", self.watchpoint_text))
+                    }
+                }
+            }
+        });
+        let mut removed = None;
+        for (index, watchpoint) in self.debugger.watchpoints.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let name = io_register_name(watchpoint.address)
+                    .map(|name| format!(" ({name})"))
+                    .unwrap_or_default();
+                ui.label(mono(format!(
+                    "{:04X}{name} {:?}",
+                    watchpoint.address, watchpoint.kind
+                )));
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+        if let Some(index) = removed {
+            self.debugger.watchpoints.remove(index);
+        }
+    }
+}
+
+impl eframe::App for DebuggerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if !self.debugger.paused {
+            self.step(Step::Frame);
+            ctx.request_repaint();
+        }
+
+        egui::SidePanel::left("cpu").show(ctx, |ui| {
+            self.controls(ui);
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::RED, error);
+            }
+            ui.separator();
+            ui.heading("Registers");
+            self.registers(ui);
+            ui.separator();
+            ui.heading("Stack");
+            self.stack(ui);
+        });
+        egui::SidePanel::right("points").show(ctx, |ui| {
+            ui.heading("Breakpoints");
+            self.breakpoints(ui);
+            ui.separator();
+            ui.heading("Watchpoints");
+            self.watchpoints(ui);
+        });
+        egui::Window::new("Memory")
+            .open(&mut self.show_memory)
+            .default_size([620.0, 480.0])
+            .show(ctx, |ui| {
+                self.memory_viewer.show(ui, &mut self.emulator.mmu)
+            });
+        egui::Window::new("Tiles")
+            .open(&mut self.show_tiles)
+            .show(ctx, |ui| self.vram_viewer.tiles(ui, &self.emulator.mmu));
+        egui::Window::new("Tilemaps")
+            .open(&mut self.show_tilemaps)
+            .show(ctx, |ui| self.vram_viewer.tilemaps(ui, &self.emulator.mmu));
+        egui::Window::new("OAM")
+            .open(&mut self.show_oam)
+            .default_height(480.0)
+            .show(ctx, |ui| self.vram_viewer.oam(ui, &self.emulator.mmu));
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Disassembly");
+            egui::ScrollArea::vertical().show(ui, |ui| self.disassembly(ui));
+        });
+    }
+}
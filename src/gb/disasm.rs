@@ -0,0 +1,314 @@
+// SM83 disassembler, the output follows RGBDS syntax so it can be pasted back into an asm file
+use std::collections::BTreeMap;
+use std::fs;
+
+use super::cpu::Bus;
+
+const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const R16_MEMORY: [&str; 4] = ["[bc]", "[de]", "[hl+]", "[hl-]"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = [
+    "add a,", "adc a,", "sub", "sbc a,", "and", "xor", "or", "cp",
+];
+const ROTATES: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Instruction {
+    // in bytes, an instruction is never empty
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+// bytes needed by each opcode, including itself
+pub fn instruction_length(opcode: u8) -> u16 {
+    match opcode {
+        0xCB => 2,
+        // d16/a16
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 | 0xEA | 0xFA => 3,
+        0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA => 3,
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => 3,
+        // d8/r8/a8
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => 2,
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 2,
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => 2,
+        0xE0 | 0xF0 | 0xE8 | 0xF8 => 2,
+        _ => 1,
+    }
+}
+
+// Labels from a RGBDS .sym file, lines look like `03:4000 SomeLabel`
+#[derive(Default)]
+pub struct Symbols {
+    labels: BTreeMap<(u8, u16), String>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            let Some((location, name)) = line.split_once(' ') else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            if let (Ok(bank), Ok(address)) = (
+                u8::from_str_radix(bank, 16),
+                u16::from_str_radix(address, 16),
+            ) {
+                symbols
+                    .labels
+                    .insert((bank, address), name.trim().to_string());
+            }
+        }
+        symbols
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Symbols> {
+        Ok(Symbols::parse(&fs::read_to_string(path)?))
+    }
+
+    // bank only matters for switchable areas, so fall back to the lowest bank with that address
+    pub fn get(&self, bank: u8, address: u16) -> Option<&str> {
+        if let Some(name) = self.labels.get(&(bank, address)) {
+            return Some(name);
+        }
+        if (0x4000..0x8000).contains(&address) {
+            return None;
+        }
+        self.labels
+            .iter()
+            .find(|((_, at), _)| *at == address)
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+// `$1234`, or the label when there's one
+fn address_operand(address: u16, bank: u8, symbols: Option<&Symbols>) -> String {
+    match symbols.and_then(|symbols| symbols.get(bank, address)) {
+        Some(name) => name.to_string(),
+        None => format!("${address:04X}"),
+    }
+}
+
+// Decodes the instruction at the start of bytes, missing bytes past the end read as 0
+pub fn decode(bytes: &[u8], address: u16, bank: u8, symbols: Option<&Symbols>) -> Instruction {
+    let byte = |index: usize| bytes.get(index).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let length = instruction_length(opcode);
+    let d8 = byte(1);
+    let d16 = byte(1) as u16 | (byte(2) as u16) << 8;
+    let a16 = address_operand(d16, bank, symbols);
+    let relative = address_operand(
+        address.wrapping_add(2).wrapping_add(d8 as i8 as u16),
+        bank,
+        symbols,
+    );
+
+    let y = (opcode >> 3) & 0b111;
+    let z = opcode & 0b111;
+    let p = (y >> 1) as usize;
+
+    let text = match opcode {
+        0x00 => "nop".to_string(),
+        0x01 | 0x11 | 0x21 | 0x31 => format!("ld {}, {}", R16[p], a16),
+        0x02 | 0x12 | 0x22 | 0x32 => format!("ld {}, a", R16_MEMORY[p]),
+        0x0A | 0x1A | 0x2A | 0x3A => format!("ld a, {}", R16_MEMORY[p]),
+        0x03 | 0x13 | 0x23 | 0x33 => format!("inc {}", R16[p]),
+        0x0B | 0x1B | 0x2B | 0x3B => format!("dec {}", R16[p]),
+        0x09 | 0x19 | 0x29 | 0x39 => format!("add hl, {}", R16[p]),
+        0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => format!("inc {}", R8[y as usize]),
+        0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => format!("dec {}", R8[y as usize]),
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+            format!("ld {}, ${d8:02X}", R8[y as usize])
+        }
+        0x07 => "rlca".to_string(),
+        0x0F => "rrca".to_string(),
+        0x17 => "rla".to_string(),
+        0x1F => "rra".to_string(),
+        0x08 => format!("ld [{a16}], sp"),
+        0x10 => "stop".to_string(),
+        0x18 => format!("jr {relative}"),
+        0x20 | 0x28 | 0x30 | 0x38 => format!("jr {}, {relative}", CONDITIONS[(y - 4) as usize]),
+        0x27 => "daa".to_string(),
+        0x2F => "cpl".to_string(),
+        0x37 => "scf".to_string(),
+        0x3F => "ccf".to_string(),
+        0x76 => "halt".to_string(),
+        0x40..=0x7F => format!("ld {}, {}", R8[y as usize], R8[z as usize]),
+        0x80..=0xBF => format!("{} {}", ALU[y as usize], R8[z as usize]),
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => format!("ret {}", CONDITIONS[y as usize]),
+        0xC1 | 0xD1 | 0xE1 | 0xF1 => format!("pop {}", R16_STACK[p]),
+        0xC5 | 0xD5 | 0xE5 | 0xF5 => format!("push {}", R16_STACK[p]),
+        0xC2 | 0xCA | 0xD2 | 0xDA => format!("jp {}, {a16}", CONDITIONS[y as usize]),
+        0xC3 => format!("jp {a16}"),
+        0xC4 | 0xCC | 0xD4 | 0xDC => format!("call {}, {a16}", CONDITIONS[y as usize]),
+        0xCD => format!("call {a16}"),
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+            format!("{} ${d8:02X}", ALU[y as usize])
+        }
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => format!("rst ${:02X}", y * 8),
+        0xC9 => "ret".to_string(),
+        0xD9 => "reti".to_string(),
+        0xCB => {
+            let cb = d8;
+            let bit = (cb >> 3) & 0b111;
+            let target = R8[(cb & 0b111) as usize];
+            match cb {
+                0x00..=0x3F => format!("{} {target}", ROTATES[bit as usize]),
+                0x40..=0x7F => format!("bit {bit}, {target}"),
+                0x80..=0xBF => format!("res {bit}, {target}"),
+                _ => format!("set {bit}, {target}"),
+            }
+        }
+        0xE0 => format!(
+            "ldh [{}], a",
+            address_operand(0xFF00 | d8 as u16, bank, symbols)
+        ),
+        0xF0 => format!(
+            "ldh a, [{}]",
+            address_operand(0xFF00 | d8 as u16, bank, symbols)
+        ),
+        0xE2 => "ldh [c], a".to_string(),
+        0xF2 => "ldh a, [c]".to_string(),
+        0xE8 => format!("add sp, {}", d8 as i8),
+        0xF8 => format!("ld hl, sp{:+}", d8 as i8),
+        0xE9 => "jp hl".to_string(),
+        0xF9 => "ld sp, hl".to_string(),
+        0xEA => format!("ld [{a16}], a"),
+        0xFA => format!("ld a, [{a16}]"),
+        0xF3 => "di".to_string(),
+        0xFB => "ei".to_string(),
+        _ => format!("db ${opcode:02X}"),
+    };
+
+    // the illegal ones are shown as data, so they only take a byte
+    let length = if text.starts_with("db") { 1 } else { length };
+    Instruction {
+        address,
+        bytes: (0..length as usize).map(byte).collect(),
+        text,
+    }
+}
+
+// Decodes straight from the emulator's memory, without side effects
+pub fn decode_at<B: Bus>(
+    bus: &B,
+    address: u16,
+    bank: u8,
+    symbols: Option<&Symbols>,
+) -> Instruction {
+    let bytes: Vec<u8> = (0..3)
+        .map(|offset| bus.peek(address.wrapping_add(offset)))
+        .collect();
+    decode(&bytes, address, bank, symbols)
+}
+
+// A line like `03:4000  C3 50 01  jp $0150`, with the label above it when there's one
+pub fn format_line(instruction: &Instruction, bank: u8, symbols: Option<&Symbols>) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect();
+    let line = format!(
+        "{bank:02X}:{:04X}  {:<9} {}",
+        instruction.address,
+        bytes.join(" "),
+        instruction.text
+    );
+    match symbols.and_then(|symbols| symbols.get(bank, instruction.address)) {
+        Some(label) => format!("{label}:\n{line}"),
+        None => line,
+    }
+}
+
+// Disassembles one bank of a rom file, bank 0 lives at 0000-3FFF and the others at 4000-7FFF
+pub fn disassemble_bank(
+    rom: &[u8],
+    bank: u8,
+    from: u16,
+    to: u32,
+    symbols: Option<&Symbols>,
+) -> Vec<String> {
+    let base = if bank == 0 { 0x0000 } else { 0x4000 };
+    let mut lines = vec![];
+    let mut address = from.max(base) as u32;
+    let end = to.min(base as u32 + 0x4000);
+    while address < end {
+        let offset = bank as usize * 0x4000 + (address as usize - base as usize);
+        if offset >= rom.len() {
+            break;
+        }
+        let instruction = decode(&rom[offset..], address as u16, bank, symbols);
+        address += instruction.size() as u32;
+        lines.push(format_line(&instruction, bank, symbols));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> String {
+        decode(bytes, 0x0150, 0, None).text
+    }
+
+    #[test]
+    fn decodes_operands() {
+        assert_eq!(text(&[0x00]), "nop");
+        assert_eq!(text(&[0x01, 0x34, 0x12]), "ld bc, $1234");
+        assert_eq!(text(&[0x2A]), "ld a, [hl+]");
+        assert_eq!(text(&[0x18, 0xFE]), "jr $0150");
+        assert_eq!(text(&[0xE0, 0x44]), "ldh [$FF44], a");
+        assert_eq!(text(&[0xF8, 0xFF]), "ld hl, sp-1");
+        assert_eq!(text(&[0xCB, 0x7E]), "bit 7, [hl]");
+        assert_eq!(text(&[0xCB, 0x37]), "swap a");
+        assert_eq!(text(&[0xD3]), "db $D3");
+        assert_eq!(text(&[0xC5]), "push bc");
+        assert_eq!(text(&[0xF5]), "push af");
+        assert_eq!(text(&[0xD1]), "pop de");
+        assert_eq!(text(&[0xE1]), "pop hl");
+        // every opcode decodes to something, none of them panic
+        for opcode in 0..=0xFF {
+            assert_eq!(
+                decode(&[opcode, 0, 0], 0, 0, None).size(),
+                instruction_length(opcode)
+            );
+            decode(&[0xCB, opcode], 0, 0, None);
+        }
+        assert_eq!(decode(&[0xCD, 0x00, 0x40], 0, 0, None).size(), 3);
+    }
+
+    #[test]
+    fn uses_symbols() {
+        let symbols =
+            Symbols::parse("; File generated by rgblink\n00:0150 Start\n03:4000 Bank3Entry\n");
+        assert_eq!(
+            decode(&[0xC3, 0x50, 0x01], 0, 0, Some(&symbols)).text,
+            "jp Start"
+        );
+        assert_eq!(
+            decode(&[0xCD, 0x00, 0x40], 0, 3, Some(&symbols)).text,
+            "call Bank3Entry"
+        );
+        assert_eq!(
+            decode(&[0xCD, 0x00, 0x40], 0, 2, Some(&symbols)).text,
+            "call $4000"
+        );
+
+        // wram labels can come with any bank, the same one wins every time
+        let symbols = Symbols::parse("02:C000 Second\n01:C000 First\n");
+        assert_eq!(symbols.get(0, 0xC000), Some("First"));
+    }
+}
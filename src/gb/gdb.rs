@@ -0,0 +1,432 @@
+// GDB remote serial protocol stub, so gdb (or anything else that speaks RSP) can debug a game
+// over a tcp socket. Breakpoints and watchpoints go through the same Debugger the egui window uses
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use super::cpu::{Bus, RegF};
+use super::debugger::{Breakpoint, Debugger, Step, StopReason, WatchKind, Watchpoint};
+use super::GameBoyEmulator;
+
+// gdb doesn't know the sm83, so it gets the registers from here: a f b c d e h l are 0-7 in the
+// same order as cpu.regs, then sp and pc
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.play-us.sm83">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="f" bitsize="8"/>
+    <reg name="b" bitsize="8"/>
+    <reg name="c" bitsize="8"/>
+    <reg name="d" bitsize="8"/>
+    <reg name="e" bitsize="8"/>
+    <reg name="h" bitsize="8"/>
+    <reg name="l" bitsize="8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+const REG_SP: usize = 8;
+const REG_PC: usize = 9;
+
+// the biggest packet gdb is told it can send us, a memory read answers with twice the bytes
+const PACKET_SIZE: usize = 0x4000;
+
+// the byte gdb sends when ctrl-c is pressed
+const INTERRUPT: u8 = 0x03;
+
+// stop replies, SIGINT and SIGTRAP
+const STOPPED_INTERRUPT: &str = "S02";
+const STOPPED_TRAP: &str = "S05";
+
+pub struct GdbStub {
+    gb: GameBoyEmulator,
+    debugger: Debugger,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, byte| sum.wrapping_add(byte))
+}
+
+// "addr,len" as sent by m, M and Z
+fn address_and_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn stop_reply(reason: Option<StopReason>) -> String {
+    match reason {
+        Some(StopReason::Watchpoint(access)) => {
+            let kind = if access.write { "watch" } else { "rwatch" };
+            format!("T05{kind}:{:04x};", access.address)
+        }
+        Some(StopReason::Breakpoint(_)) => "T05swbreak:;".to_string(),
+        // SIGILL
+        Some(StopReason::Locked(_)) => "S04".to_string(),
+        None => STOPPED_TRAP.to_string(),
+    }
+}
+
+impl GdbStub {
+    pub fn new(gb: GameBoyEmulator) -> GdbStub {
+        GdbStub {
+            gb,
+            debugger: Debugger::new(),
+        }
+    }
+
+    // talks to one client until it detaches, kills the session or hangs up
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        while let Some(packet) = read_packet(&mut stream)? {
+            match packet.as_str() {
+                // nothing to answer
+                "k" => return Ok(()),
+                "D" => {
+                    send_packet(&mut stream, "OK")?;
+                    return Ok(());
+                }
+                _ => {
+                    let reply = self.handle(&packet, &mut stream)?;
+                    send_packet(&mut stream, &reply)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str, stream: &mut TcpStream) -> io::Result<String> {
+        // the first char, which isn't always a byte when the client sends garbage
+        let split = packet.chars().next().map_or(0, char::len_utf8);
+        let (command, args) = packet.split_at(split);
+        let reply = match command {
+            "?" => STOPPED_TRAP.to_string(),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|register| self.read_register(register))
+                .unwrap_or_else(|| "E01".to_string()),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.set_point(command == "Z", args),
+            "s" => {
+                self.jump(args);
+                stop_reply(self.debugger.run(&mut self.gb, Step::Instruction))
+            }
+            "c" => {
+                self.jump(args);
+                self.resume(stream)?
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            // anything empty means "not supported" to gdb
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;swbreak+")
+        } else if args == "Attached" {
+            "1".to_string()
+        } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Ok(offset), Ok(length)) = (
+                usize::from_str_radix(offset, 16),
+                usize::from_str_radix(length, 16),
+            ) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = (start + length).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { "m" } else { "l" };
+            format!("{more}{}", &TARGET_XML[start..end])
+        } else {
+            String::new()
+        }
+    }
+
+    // c and s can come with the address to start from
+    fn jump(&mut self, args: &str) {
+        if let Ok(address) = u16::from_str_radix(args, 16) {
+            self.gb.cpu.pc = address;
+        }
+    }
+
+    // runs a frame at a time, checking for ctrl-c in between
+    fn resume(&mut self, stream: &mut TcpStream) -> io::Result<String> {
+        loop {
+            if let Some(reason) = self.debugger.run(&mut self.gb, Step::Frame) {
+                return Ok(stop_reply(Some(reason)));
+            }
+            stream.set_nonblocking(true)?;
+            let mut byte = [0];
+            let read = stream.read(&mut byte);
+            stream.set_nonblocking(false)?;
+            match read {
+                Ok(1) if byte[0] == INTERRUPT => return Ok(STOPPED_INTERRUPT.to_string()),
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn read_register(&self, register: usize) -> Option<String> {
+        let cpu = &self.gb.cpu;
+        match register {
+            0..=7 => Some(hex(&[cpu.regs[register]])),
+            REG_SP => Some(hex(&cpu.sp.to_le_bytes())),
+            REG_PC => Some(hex(&cpu.pc.to_le_bytes())),
+            _ => None,
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..=REG_PC)
+            .filter_map(|register| self.read_register(register))
+            .collect()
+    }
+
+    fn set_register(&mut self, register: usize, value: &[u8]) -> Option<()> {
+        let cpu = &mut self.gb.cpu;
+        match (register, value) {
+            // the low nibble of F doesn't exist
+            (0..=7, [value]) if register == RegF => cpu.regs[RegF] = value & 0xF0,
+            (0..=7, [value]) => cpu.regs[register] = *value,
+            (REG_SP, [low, high]) => cpu.sp = u16::from_le_bytes([*low, *high]),
+            (REG_PC, [low, high]) => cpu.pc = u16::from_le_bytes([*low, *high]),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let written = args.split_once('=').and_then(|(register, value)| {
+            let register = usize::from_str_radix(register, 16).ok()?;
+            self.set_register(register, &unhex(value)?)
+        });
+        match written {
+            Some(()) => "OK".to_string(),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let Some(bytes) = unhex(args) else {
+            return "E01".to_string();
+        };
+        if bytes.len() < 12 {
+            return "E01".to_string();
+        }
+        for register in 0..8 {
+            self.set_register(register, &bytes[register..=register]);
+        }
+        self.set_register(REG_SP, &bytes[8..10]);
+        self.set_register(REG_PC, &bytes[10..12]);
+        "OK".to_string()
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((address, length)) = address_and_length(args) else {
+            return "E01".to_string();
+        };
+        if length > PACKET_SIZE / 2 {
+            return "E01".to_string();
+        }
+        let bytes: Vec<u8> = (0..length)
+            .map(|offset| self.gb.mmu.peek(address.wrapping_add(offset as u16)))
+            .collect();
+        hex(&bytes)
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let written = args.split_once(':').and_then(|(range, data)| {
+            let (address, length) = address_and_length(range)?;
+            let bytes = unhex(data).filter(|bytes| bytes.len() == length)?;
+            for (offset, byte) in bytes.into_iter().enumerate() {
+                self.gb.mmu.poke(address.wrapping_add(offset as u16), byte);
+            }
+            Some(())
+        });
+        match written {
+            Some(()) => "OK".to_string(),
+            None => "E01".to_string(),
+        }
+    }
+
+    // Z0/Z1 are breakpoints, Z2 Z3 Z4 are write, read and access watchpoints
+    fn set_point(&mut self, insert: bool, args: &str) -> String {
+        let Some((kind, range)) = args.split_once(',') else {
+            return "E01".to_string();
+        };
+        let Some((address, length)) = address_and_length(range) else {
+            return "E01".to_string();
+        };
+
+        let watch_kind = match kind {
+            "0" | "1" => {
+                let breakpoint = Breakpoint {
+                    bank: None,
+                    address,
+                };
+                let breakpoints = &mut self.debugger.breakpoints;
+                breakpoints.retain(|at| *at != breakpoint);
+                if insert {
+                    breakpoints.push(breakpoint);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        for offset in 0..length.max(1) {
+            let watchpoint = Watchpoint {
+                address: address.wrapping_add(offset as u16),
+                kind: watch_kind,
+            };
+            let watchpoints = &mut self.debugger.watchpoints;
+            watchpoints.retain(|at| *at != watchpoint);
+            if insert {
+                watchpoints.push(watchpoint);
+            }
+        }
+        "OK".to_string()
+    }
+}
+
+// Waits for the next $packet#xx, acking it. Stray acks and ctrl-c while stopped are skipped.
+// None once the client hangs up
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut byte = [0];
+    loop {
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = vec![];
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut sum = [0; 2];
+        stream.read_exact(&mut sum)?;
+
+        let data = String::from_utf8_lossy(&data).to_string();
+        let sum = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if sum == Some(checksum(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(data));
+        }
+        stream.write_all(b"-")?;
+    }
+}
+
+fn send_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    write!(stream, "${data}#{:02x}", checksum(data))?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::gb::GbModel;
+    use std::thread;
+
+    // just enough of a client to talk to the stub the way gdb does
+    fn request(stream: &mut TcpStream, data: &str) -> String {
+        send_packet(stream, data).unwrap();
+        let mut ack = [0];
+        stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+        read_packet(stream).unwrap().unwrap()
+    }
+
+    #[test]
+    fn talks_rsp() {
+        // ld a, $42; ld [$C000], a; jr -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x107].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+        let mut gb = GameBoyEmulator::with_model(GbModel::Dmg, false);
+        gb.load_rom(&rom).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || GdbStub::new(gb).serve(&listener));
+        let mut client = TcpStream::connect(address).unwrap();
+
+        assert!(request(&mut client, "qSupported:swbreak+").contains("qXfer:features:read+"));
+        let xml = request(&mut client, "qXfer:features:read:target.xml:0,1000");
+        assert!(xml.starts_with('l') && xml.contains("org.play-us.sm83"));
+        assert_eq!(request(&mut client, "?"), "S05");
+
+        // dmg boot state, pc is the last register
+        let registers = request(&mut client, "g");
+        assert_eq!(registers, "01b0001300d8014dfeff0001");
+        assert_eq!(request(&mut client, "m0100,3"), "3e42ea");
+        // more than fits in a packet
+        assert_eq!(request(&mut client, "m0,ffffffff"), "E01");
+
+        assert_eq!(request(&mut client, "s"), "S05");
+        assert_eq!(request(&mut client, "p0"), "42");
+        assert_eq!(request(&mut client, "P2=99"), "OK");
+        assert_eq!(request(&mut client, "p2"), "99");
+
+        assert_eq!(request(&mut client, "Z2,c000,1"), "OK");
+        assert_eq!(request(&mut client, "c"), "T05watch:c000;");
+        assert_eq!(request(&mut client, "z2,c000,1"), "OK");
+
+        assert_eq!(request(&mut client, "Z0,105,1"), "OK");
+        assert_eq!(request(&mut client, "c"), "T05swbreak:;");
+        assert_eq!(request(&mut client, "p9"), "0501");
+
+        // not a command, and not even ascii
+        assert_eq!(request(&mut client, "\u{e9}t\u{e9}"), "");
+
+        assert_eq!(request(&mut client, "Mc100,2:abcd"), "OK");
+        assert_eq!(request(&mut client, "mc100,2"), "abcd");
+
+        assert_eq!(request(&mut client, "D"), "OK");
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+use super::cpu::{Bus, IF_ADDRESS};
+use super::debugger::Access;
+use super::model::Quirks;
+use super::ppu::{self, OamCorruption, Ppu};
+use super::printlnme;
+use crate::emulator::{Button, ButtonState};
+use crate::memory_viewer::MemoryView;
+use crate::save_state::{Chunk, ChunkReader, StateError};
+
+// the joypad, bits 4-5 pick a row of buttons and bits 0-3 read it back
+pub const P1: usize = 0xFF00;
+// cgb only, picks the vram bank mapped at 8000-9FFF
+pub const VBK: usize = 0xFF4F;
+
+pub struct Mmu {
+    pub memory: [u8; 1024 * 64],
+    // whichever vram bank isn't mapped right now, so the flat memory always has the mapped one
+    other_vram: [u8; 0x2000],
+    pub cgb: bool,
+    pub ppu: Ppu,
+    pub quirks: Quirks,
+    buttons: ButtonState,
+    // every cpu read and write gets recorded here while the debugger is watching
+    pub access_log: Option<Vec<Access>>,
+}
+
+impl Mmu {
+    pub fn new() -> Mmu {
+        let mut memory = [0; 1024 * 64];
+        // what the boot rom leaves behind, the lcd is already on at this point
+        memory[ppu::LCDC] = 0x91;
+        memory[ppu::STAT] = 0x85;
+        memory[ppu::BGP] = 0xFC;
+        memory[P1] = 0xCF;
+
+        Mmu {
+            memory,
+            other_vram: [0; 0x2000],
+            cgb: false,
+            ppu: Ppu::new(),
+            quirks: Quirks::default(),
+            buttons: ButtonState::default(),
+            access_log: None,
+        }
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        self.memory[0..rom.len()].copy_from_slice(rom);
+    }
+
+    // the whole address space, rom included, there's no mapper yet to tell rom and ram apart
+    pub fn save(&self, chunk: &mut Chunk) {
+        chunk.bytes(&self.memory);
+        chunk.bytes(&self.other_vram);
+    }
+
+    pub fn load(&mut self, chunk: &mut ChunkReader) -> Result<(), StateError> {
+        chunk.bytes_into(&mut self.memory)?;
+        chunk.bytes_into(&mut self.other_vram)
+    }
+
+    // the rom bank mapped at an address, without bank switching 4000-7FFF is always bank 1
+    pub fn rom_bank(&self, address: u16) -> u8 {
+        match address {
+            0x4000..=0x7FFF => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn vram_banks(&self) -> usize {
+        if self.cgb {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn vram_bank(&self) -> usize {
+        if self.cgb {
+            self.memory[VBK] as usize & 1
+        } else {
+            0
+        }
+    }
+
+    // the 8000-9FFF contents of a vram bank, mapped or not
+    pub fn vram(&self, bank: usize) -> &[u8] {
+        if bank == self.vram_bank() {
+            &self.memory[0x8000..0xA000]
+        } else {
+            &self.other_vram
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: ButtonState) {
+        let old_value = self.memory[P1];
+        self.buttons = buttons;
+        self.update_joypad();
+        // a button going down in a selected row asks for the joypad interrupt
+        if old_value & !self.memory[P1] & 0x0F != 0 {
+            self.memory[IF_ADDRESS as usize] |= 0x10;
+        }
+    }
+
+    // for some goddamn reason, in gameboy, a not pressed button is 1 and a pressed button is 0.
+    // The row select bits work the same way, 0 picks the row
+    fn update_joypad(&mut self) {
+        let select = self.memory[P1] & 0x30;
+        let rows = [
+            (
+                0x10,
+                [Button::Right, Button::Left, Button::Up, Button::Down],
+            ),
+            (0x20, [Button::A, Button::B, Button::Select, Button::Start]),
+        ];
+        let mut pressed = 0;
+        for (row, buttons) in rows {
+            if select & row == 0 {
+                for (bit, button) in buttons.into_iter().enumerate() {
+                    if self.buttons.pressed(button) {
+                        pressed |= 1 << bit;
+                    }
+                }
+            }
+        }
+        self.memory[P1] = 0xC0 | select | (!pressed & 0x0F);
+    }
+
+    fn set_hram(&mut self, address: usize, value: u8) {
+        let old_value = self.memory[address + 0xFF00];
+        self.memory[address + 0xFF00] = value;
+        printlnme(format!(
+            "Set {:02X} at {:02X} (or {}) of hram",
+            value,
+            address,
+            address + 0xFF00
+        ));
+
+        // turns out this high area is used for a ton of flags that the hardware plays with
+        match address {
+            0x0000 => {
+                // only the row bits can be written, the buttons come from set_buttons
+                self.memory[P1] = 0xC0 | (value & 0x30);
+                self.update_joypad();
+            }
+            0x0041 => {
+                self.memory[ppu::STAT] = old_value;
+                self.ppu
+                    .write_stat(&mut self.memory, value, self.quirks.stat_write_bug);
+            }
+            // LY is read only
+            0x0044 => self.memory[ppu::LY] = old_value,
+            0x004F if self.cgb => {
+                let bank = value & 1;
+                if bank != old_value & 1 {
+                    self.memory[0x8000..0xA000].swap_with_slice(&mut self.other_vram);
+                }
+                self.memory[VBK] = 0xFE | bank;
+            }
+            _ => printlnme(format!("set_hram should check for {:04X}", address)),
+        }
+    }
+    fn get_hram(&mut self, address: usize) -> u8 {
+        printlnme(format!(
+            "Got {:02X} at {:02X} (or {}) of hram",
+            self.memory[address + 0xFF00],
+            address,
+            address + 0xFF00
+        ));
+        self.memory[address + 0xFF00]
+    }
+}
+
+impl Bus for Mmu {
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        let value = match address {
+            0xFF00..=0xFFFF => self.get_hram(address as usize - 0xFF00),
+            _ => self.memory[address as usize],
+        };
+        if let Some(log) = &mut self.access_log {
+            log.push(Access {
+                address,
+                value,
+                write: false,
+            });
+        }
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        if let Some(log) = &mut self.access_log {
+            log.push(Access {
+                address,
+                value,
+                write: true,
+            });
+        }
+        match address {
+            // for now it doesn't matter, the tetris rom doesn't have bank switching, so writes
+            // to the rom just go nowhere
+            0x0000..=0x7FFF => {}
+            0xFF00..=0xFFFF => self.set_hram(address as usize - 0xFF00, value),
+            _ => self.memory[address as usize] = value,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ppu.tick(&mut self.memory);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn oam_bug(&mut self, address: u16, kind: OamCorruption) {
+        if !self.quirks.oam_bug || !(0xFE00..=0xFEFF).contains(&address) {
+            return;
+        }
+        if let Some(row) = self.ppu.oam_row() {
+            ppu::corrupt_oam(&mut self.memory[0xFE00..0xFEA0], row, kind);
+        }
+    }
+}
+
+impl MemoryView for Mmu {
+    fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn peek(&self, address: usize) -> u8 {
+        Bus::peek(self, address as u16)
+    }
+
+    fn poke(&mut self, address: usize, value: u8) {
+        Bus::poke(self, address as u16, value)
+    }
+
+    fn region(&self, address: usize) -> String {
+        match address {
+            0x0000..=0x3FFF => "ROM0".to_string(),
+            0x4000..=0x7FFF => format!("ROMX {:02X}", self.rom_bank(address as u16)),
+            0x8000..=0x9FFF => "VRAM".to_string(),
+            0xA000..=0xBFFF => "SRAM".to_string(),
+            0xC000..=0xDFFF => "WRAM".to_string(),
+            0xE000..=0xFDFF => "ECHO".to_string(),
+            0xFE00..=0xFE9F => "OAM".to_string(),
+            0xFEA0..=0xFEFF => "----".to_string(),
+            0xFF00..=0xFF7F => "IO".to_string(),
+            0xFF80..=0xFFFE => "HRAM".to_string(),
+            _ => "IE".to_string(),
+        }
+    }
+}
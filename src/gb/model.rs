@@ -0,0 +1,53 @@
+// The different game boys behave slightly differently, mostly in bugs that a handful of games
+// (and a lot of test roms) depend on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GbModel {
+    Dmg, // the original
+    Mgb, // game boy pocket
+    Cgb, // game boy color, but only its dmg compatible side (and the second vram bank) for now
+}
+
+impl GbModel {
+    pub fn from_name(name: &str) -> Option<GbModel> {
+        match name.to_lowercase().as_str() {
+            "dmg" => Some(GbModel::Dmg),
+            "mgb" => Some(GbModel::Mgb),
+            "cgb" => Some(GbModel::Cgb),
+            _ => None,
+        }
+    }
+
+    // what the boot rom leaves in AF, BC, DE and HL
+    pub fn initial_regs(&self) -> [u8; 8] {
+        match self {
+            GbModel::Dmg => [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            GbModel::Mgb => [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            GbModel::Cgb => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        }
+    }
+}
+
+// Hardware bugs that normal games don't care about, they're only emulated when asking for accuracy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    // https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+    pub oam_bug: bool,
+    // writing to STAT acts like writing 0xFF for a cycle, firing a STAT interrupt
+    pub stat_write_bug: bool,
+    // HALT with IME off and an interrupt pending makes the cpu read the next byte twice
+    pub halt_bug: bool,
+}
+
+impl Quirks {
+    pub fn for_model(model: GbModel, accurate: bool) -> Quirks {
+        if !accurate {
+            return Quirks::default();
+        }
+        let dmg_like = matches!(model, GbModel::Dmg | GbModel::Mgb);
+        Quirks {
+            oam_bug: dmg_like,
+            stat_write_bug: dmg_like,
+            halt_bug: true, // every model has this one
+        }
+    }
+}
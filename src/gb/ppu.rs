@@ -0,0 +1,275 @@
+// For now it only keeps the timing of the lcd: modes, LY and the STAT interrupts
+// https://gbdev.io/pandocs/Rendering.html
+use crate::save_state::{Chunk, ChunkReader, StateError};
+
+pub const LCDC: usize = 0xFF40;
+pub const STAT: usize = 0xFF41;
+pub const SCY: usize = 0xFF42;
+pub const SCX: usize = 0xFF43;
+pub const LY: usize = 0xFF44;
+pub const LYC: usize = 0xFF45;
+pub const BGP: usize = 0xFF47;
+pub const OBP0: usize = 0xFF48;
+pub const OBP1: usize = 0xFF49;
+const IF: usize = 0xFF0F;
+
+pub const MODE_HBLANK: u8 = 0;
+pub const MODE_VBLANK: u8 = 1;
+pub const MODE_OAM_SCAN: u8 = 2;
+pub const MODE_DRAWING: u8 = 3;
+
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+// mode 3 actually changes length with sprites and scrolling, but this is the shortest it gets
+const DRAWING_DOTS: u16 = 172;
+
+pub struct Ppu {
+    pub mode: u8,
+    // dots (clock cycles) into the current line
+    pub dots: u16,
+    stat_line: bool,
+    // lines and frames drawn since power on, handy to know when one ended
+    pub lines: u64,
+    pub frames: u64,
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            mode: MODE_OAM_SCAN,
+            dots: 0,
+            stat_line: false,
+            lines: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn save(&self, chunk: &mut Chunk) {
+        chunk.u8(self.mode);
+        chunk.u16(self.dots);
+        chunk.bool(self.stat_line);
+        chunk.u64(self.lines);
+        chunk.u64(self.frames);
+    }
+
+    pub fn load(&mut self, chunk: &mut ChunkReader) -> Result<(), StateError> {
+        self.mode = chunk.u8()?;
+        self.dots = chunk.u16()?;
+        self.stat_line = chunk.bool()?;
+        self.lines = chunk.u64()?;
+        self.frames = chunk.u64()?;
+        Ok(())
+    }
+
+    pub fn lcd_on(memory: &[u8]) -> bool {
+        memory[LCDC] & 0x80 != 0
+    }
+
+    // one M-cycle
+    pub fn tick(&mut self, memory: &mut [u8]) {
+        if !Ppu::lcd_on(memory) {
+            // turning the lcd off resets it to the top of the screen
+            memory[LY] = 0;
+            self.dots = 0;
+            self.mode = MODE_HBLANK;
+            self.stat_line = false;
+            self.update_stat(memory);
+            return;
+        }
+
+        self.dots += 4;
+        if self.dots >= DOTS_PER_LINE {
+            self.dots -= DOTS_PER_LINE;
+            self.lines += 1;
+            memory[LY] += 1;
+            if memory[LY] == 144 {
+                // request vblink interrupt
+                memory[IF] |= 1;
+                self.frames += 1;
+            }
+            if memory[LY] == 154 {
+                memory[LY] = 0;
+            }
+        }
+
+        self.mode = if memory[LY] >= 144 {
+            MODE_VBLANK
+        } else if self.dots < OAM_SCAN_DOTS {
+            MODE_OAM_SCAN
+        } else if self.dots < OAM_SCAN_DOTS + DRAWING_DOTS {
+            MODE_DRAWING
+        } else {
+            MODE_HBLANK
+        };
+        self.update_stat(memory);
+    }
+
+    pub fn lyc_match(memory: &[u8]) -> bool {
+        memory[LY] == memory[LYC]
+    }
+
+    fn update_stat(&mut self, memory: &mut [u8]) {
+        let lyc_match = Ppu::lyc_match(memory);
+        memory[STAT] = 0x80 | (memory[STAT] & 0x78) | ((lyc_match as u8) << 2) | self.mode;
+
+        // all the STAT sources are or-ed into one line, the interrupt only fires when it goes up
+        let enabled = memory[STAT];
+        let line = Ppu::lcd_on(memory)
+            && ((self.mode == MODE_HBLANK && enabled & 0x08 != 0)
+                || (self.mode == MODE_VBLANK && enabled & 0x10 != 0)
+                || (self.mode == MODE_OAM_SCAN && enabled & 0x20 != 0)
+                || (lyc_match && enabled & 0x40 != 0));
+        if line && !self.stat_line {
+            memory[IF] |= 0b10;
+        }
+        self.stat_line = line;
+    }
+
+    pub fn write_stat(&mut self, memory: &mut [u8], value: u8, stat_write_bug: bool) {
+        if stat_write_bug && Ppu::lcd_on(memory) {
+            // for a cycle every source is enabled, so anything but mode 2/3 without LYC fires it
+            let fires =
+                self.mode == MODE_HBLANK || self.mode == MODE_VBLANK || Ppu::lyc_match(memory);
+            if fires && !self.stat_line {
+                memory[IF] |= 0b10;
+            }
+        }
+        // the lower 3 bits are read only
+        memory[STAT] = (memory[STAT] & 0x07) | (value & 0x78) | 0x80;
+        self.update_stat(memory);
+    }
+
+    // the oam row being read during mode 2, the ppu goes through one row (2 sprites) per M-cycle
+    pub fn oam_row(&self) -> Option<usize> {
+        match self.mode {
+            MODE_OAM_SCAN => Some((self.dots / 4) as usize),
+            _ => None,
+        }
+    }
+}
+
+// the color indices (0-3) of one 2bpp row of a tile, leftmost pixel first
+// https://gbdev.io/pandocs/Tile_Data.html
+pub fn tile_row(low: u8, high: u8) -> [u8; 8] {
+    let mut row = [0; 8];
+    for (x, color) in row.iter_mut().enumerate() {
+        let bit = 7 - x;
+        *color = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+    }
+    row
+}
+
+// the shade (0 white to 3 black) a palette like BGP gives to a color index
+pub fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 3
+}
+
+// the shade of every color index, sprites don't draw color 0 so it has none
+pub fn palette_shades(palette: u8, transparent: bool) -> [Option<u8>; 4] {
+    let mut shades = [None; 4];
+    for (color, slot) in shades.iter_mut().enumerate() {
+        if !(transparent && color == 0) {
+            *slot = Some(shade(palette, color as u8));
+        }
+    }
+    shades
+}
+
+// which of the 384 tiles a tilemap entry points to, LCDC bit 4 picks between the 8000 and the
+// signed 8800 addressing
+pub fn map_tile(lcdc: u8, index: u8) -> usize {
+    if lcdc & 0x10 != 0 {
+        index as usize
+    } else {
+        (256 + index as i8 as isize) as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OamCorruption {
+    Write,
+    Read,
+    ReadIncrease,
+}
+
+// https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+// the oam is seen as 20 rows of 4 words (16 bit) here
+pub fn corrupt_oam(oam: &mut [u8], row: usize, kind: OamCorruption) {
+    // the first row is never touched since the pattern always needs the row before it
+    if row == 0 || row >= 20 {
+        return;
+    }
+    let word = |oam: &[u8], row: usize, index: usize| -> u16 {
+        let at = row * 8 + index * 2;
+        oam[at] as u16 | (oam[at + 1] as u16) << 8
+    };
+    let set_word = |oam: &mut [u8], row: usize, index: usize, value: u16| {
+        let at = row * 8 + index * 2;
+        oam[at] = (value & 0xFF) as u8;
+        oam[at + 1] = (value >> 8) as u8;
+    };
+    let copy_row = |oam: &mut [u8], from: usize, to: usize, first_word: usize| {
+        let (from, to) = (from * 8 + first_word * 2, to * 8 + first_word * 2);
+        let len = 8 - first_word * 2;
+        oam.copy_within(from..from + len, to);
+    };
+
+    match kind {
+        OamCorruption::Write => {
+            let (a, b, c) = (
+                word(oam, row, 0),
+                word(oam, row - 1, 0),
+                word(oam, row - 1, 2),
+            );
+            set_word(oam, row, 0, ((a ^ c) & (b ^ c)) ^ c);
+            copy_row(oam, row - 1, row, 1);
+        }
+        OamCorruption::Read => {
+            let (a, b, c) = (
+                word(oam, row, 0),
+                word(oam, row - 1, 0),
+                word(oam, row - 1, 2),
+            );
+            set_word(oam, row, 0, b | (a & c));
+            copy_row(oam, row - 1, row, 1);
+        }
+        OamCorruption::ReadIncrease => {
+            if (4..19).contains(&row) {
+                let a = word(oam, row - 2, 0);
+                let b = word(oam, row - 1, 0);
+                let c = word(oam, row, 0);
+                let d = word(oam, row - 1, 2);
+                set_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+                copy_row(oam, row - 1, row, 0);
+                copy_row(oam, row - 1, row - 2, 0);
+            }
+            corrupt_oam(oam, row, OamCorruption::Read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_tiles() {
+        // the first row of the example in pandocs
+        assert_eq!(tile_row(0x3C, 0x7E), [0, 2, 3, 3, 3, 3, 2, 0]);
+        assert_eq!(shade(0xE4, 2), 2);
+        assert_eq!(shade(0x1B, 0), 3);
+        assert_eq!(map_tile(0x91, 0x80), 0x80);
+        assert_eq!(map_tile(0x81, 0x80), 0x80);
+        assert_eq!(map_tile(0x81, 0x00), 0x100);
+        assert_eq!(map_tile(0x81, 0x7F), 0x17F);
+    }
+
+    #[test]
+    fn transparent_color_zero() {
+        assert_eq!(
+            palette_shades(0xE4, true),
+            [None, Some(1), Some(2), Some(3)]
+        );
+        assert_eq!(palette_shades(0x1B, false)[0], Some(3));
+    }
+}
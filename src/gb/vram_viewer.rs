@@ -0,0 +1,267 @@
+// Tile, tilemap and OAM viewers for the debugger. Everything is decoded again from vram every
+// time it's drawn, so it follows what the game is doing
+use eframe::egui::{self, Color32, ColorImage, RichText, TextureHandle, TextureOptions};
+
+use super::mmu::Mmu;
+use super::ppu;
+
+// white to black, like a dmg without the green
+const SHADES: [Color32; 4] = [
+    Color32::from_rgb(0xFF, 0xFF, 0xFF),
+    Color32::from_rgb(0xAA, 0xAA, 0xAA),
+    Color32::from_rgb(0x55, 0x55, 0x55),
+    Color32::from_rgb(0x00, 0x00, 0x00),
+];
+const TILES_PER_BANK: usize = 384;
+const TILES_PER_ROW: usize = 16;
+const SCALE: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Palette {
+    Bgp,
+    Obp0,
+    Obp1,
+    // the color indices as they are, no palette
+    Raw,
+}
+
+impl Palette {
+    fn value(&self, mmu: &Mmu) -> u8 {
+        match self {
+            Palette::Bgp => mmu.memory[ppu::BGP],
+            Palette::Obp0 => mmu.memory[ppu::OBP0],
+            Palette::Obp1 => mmu.memory[ppu::OBP1],
+            Palette::Raw => 0xE4,
+        }
+    }
+}
+
+pub struct VramViewer {
+    palette: Palette,
+    tiles: Option<TextureHandle>,
+    maps: [Option<TextureHandle>; 2],
+    sprites: Vec<TextureHandle>,
+}
+
+// keeps reusing the same texture instead of making a new one every frame
+fn upload<'a>(
+    ui: &egui::Ui,
+    slot: &'a mut Option<TextureHandle>,
+    name: &str,
+    image: ColorImage,
+) -> &'a TextureHandle {
+    match slot {
+        Some(texture) => texture.set(image, TextureOptions::NEAREST),
+        None => *slot = Some(ui.ctx().load_texture(name, image, TextureOptions::NEAREST)),
+    }
+    slot.as_ref().unwrap()
+}
+
+fn show_texture(ui: &mut egui::Ui, texture: &TextureHandle) -> egui::Response {
+    ui.image((texture.id(), texture.size_vec2() * SCALE))
+}
+
+// draws the color indices of a tile into an image, None for the colors that are transparent
+fn draw_tile(
+    image: &mut ColorImage,
+    vram: &[u8],
+    tile: usize,
+    at: (usize, usize),
+    colors: [Option<Color32>; 4],
+    (flip_x, flip_y): (bool, bool),
+) {
+    for y in 0..8 {
+        let line = if flip_y { 7 - y } else { y };
+        let row = tile * 16 + line * 2;
+        let pixels = ppu::tile_row(vram[row], vram[row + 1]);
+        for x in 0..8 {
+            let color = pixels[if flip_x { 7 - x } else { x }];
+            if let Some(color) = colors[color as usize] {
+                image[(at.0 + x, at.1 + y)] = color;
+            }
+        }
+    }
+}
+
+fn palette_colors(palette: u8, transparent: bool) -> [Option<Color32>; 4] {
+    ppu::palette_shades(palette, transparent).map(|shade| shade.map(|at| SHADES[at as usize]))
+}
+
+impl VramViewer {
+    pub fn new() -> VramViewer {
+        VramViewer {
+            palette: Palette::Bgp,
+            tiles: None,
+            maps: [None, None],
+            sprites: vec![],
+        }
+    }
+
+    pub fn tiles(&mut self, ui: &mut egui::Ui, mmu: &Mmu) {
+        egui::ComboBox::from_label("Palette")
+            .selected_text(format!("{:?}", self.palette))
+            .show_ui(ui, |ui| {
+                for palette in [Palette::Bgp, Palette::Obp0, Palette::Obp1, Palette::Raw] {
+                    ui.selectable_value(&mut self.palette, palette, format!("{palette:?}"));
+                }
+            });
+
+        // every vram bank gets its own block of 16 columns, side by side
+        let banks = mmu.vram_banks();
+        let rows = TILES_PER_BANK / TILES_PER_ROW;
+        let mut image = ColorImage::new(
+            [TILES_PER_ROW * 8 * banks, rows * 8],
+            Color32::from_rgb(0xFF, 0x00, 0xFF),
+        );
+        let colors = palette_colors(self.palette.value(mmu), false);
+        for bank in 0..banks {
+            let vram = mmu.vram(bank);
+            for tile in 0..TILES_PER_BANK {
+                let at = (
+                    (bank * TILES_PER_ROW + tile % TILES_PER_ROW) * 8,
+                    tile / TILES_PER_ROW * 8,
+                );
+                draw_tile(&mut image, vram, tile, at, colors, (false, false));
+            }
+        }
+
+        let texture = upload(ui, &mut self.tiles, "tiles", image);
+        let response = show_texture(ui, texture);
+        if let Some(pointer) = response.hover_pos() {
+            let position = (pointer - response.rect.min) / (8.0 * SCALE);
+            let (column, row) = (position.x as usize, position.y as usize);
+            let bank = column / TILES_PER_ROW;
+            let tile = row * TILES_PER_ROW + column % TILES_PER_ROW;
+            if bank < banks && tile < TILES_PER_BANK {
+                response.on_hover_text(format!(
+                    "tile {tile:03X} at {}:{:04X}",
+                    bank,
+                    0x8000 + tile * 16
+                ));
+            }
+        }
+    }
+
+    pub fn tilemaps(&mut self, ui: &mut egui::Ui, mmu: &Mmu) {
+        let lcdc = mmu.memory[ppu::LCDC];
+        let (scx, scy) = (mmu.memory[ppu::SCX] as f32, mmu.memory[ppu::SCY] as f32);
+        let colors = palette_colors(mmu.memory[ppu::BGP], false);
+        let vram = mmu.vram(0);
+        let background = ((lcdc >> 3) & 1) as usize;
+
+        ui.horizontal(|ui| {
+            for (map, slot) in self.maps.iter_mut().enumerate() {
+                let base = 0x1800 + map * 0x400;
+                let mut image = ColorImage::new([256, 256], Color32::BLACK);
+                for index in 0..32 * 32 {
+                    let tile = ppu::map_tile(lcdc, vram[base + index]);
+                    let at = (index % 32 * 8, index / 32 * 8);
+                    draw_tile(&mut image, vram, tile, at, colors, (false, false));
+                }
+
+                ui.vertical(|ui| {
+                    let label = format!("{:04X}", 0x8000 + base);
+                    if map == background {
+                        ui.label(RichText::new(format!("{label} (background)")).strong());
+                    } else {
+                        ui.label(label);
+                    }
+                    let texture = upload(ui, slot, &format!("map {map}"), image);
+                    let response = show_texture(ui, texture);
+                    if map != background {
+                        return;
+                    }
+
+                    // the screen wraps around the map, so the viewport can be cut in 4 pieces
+                    let painter = ui.painter().with_clip_rect(response.rect);
+                    let stroke = egui::Stroke::new(2.0, Color32::RED);
+                    for dx in [0.0, -256.0] {
+                        for dy in [0.0, -256.0] {
+                            let min = response.rect.min + egui::vec2(scx + dx, scy + dy) * SCALE;
+                            let rect =
+                                egui::Rect::from_min_size(min, egui::vec2(160.0, 144.0) * SCALE);
+                            painter.rect_stroke(rect, 0.0, stroke);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    pub fn oam(&mut self, ui: &mut egui::Ui, mmu: &Mmu) {
+        let lcdc = mmu.memory[ppu::LCDC];
+        let height = if lcdc & 0x04 != 0 { 16 } else { 8 };
+        let oam = &mmu.memory[0xFE00..0xFEA0];
+
+        let mut images = vec![];
+        for sprite in oam.chunks(4) {
+            let flags = sprite[3];
+            let palette = if flags & 0x10 != 0 {
+                mmu.memory[ppu::OBP1]
+            } else {
+                mmu.memory[ppu::OBP0]
+            };
+            let vram = mmu.vram(if mmu.cgb {
+                (flags as usize >> 3) & 1
+            } else {
+                0
+            });
+            let flip = (flags & 0x20 != 0, flags & 0x40 != 0);
+            let mut image = ColorImage::new([8, height], Color32::from_gray(0x30));
+            let colors = palette_colors(palette, true);
+            if height == 16 {
+                // 8x16 sprites ignore the lowest bit of the tile, and flipping swaps the halves
+                let (top, bottom) = (sprite[2] as usize & 0xFE, sprite[2] as usize | 1);
+                let (top, bottom) = if flip.1 { (bottom, top) } else { (top, bottom) };
+                draw_tile(&mut image, vram, top, (0, 0), colors, flip);
+                draw_tile(&mut image, vram, bottom, (0, 8), colors, flip);
+            } else {
+                draw_tile(&mut image, vram, sprite[2] as usize, (0, 0), colors, flip);
+            }
+            images.push(image);
+        }
+
+        for (index, image) in images.into_iter().enumerate() {
+            match self.sprites.get_mut(index) {
+                Some(texture) => texture.set(image, TextureOptions::NEAREST),
+                None => {
+                    let texture = ui.ctx().load_texture(
+                        format!("sprite {index}"),
+                        image,
+                        TextureOptions::NEAREST,
+                    );
+                    self.sprites.push(texture);
+                }
+            }
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("oam").striped(true).show(ui, |ui| {
+                for title in ["#", "", "Y", "X", "Tile", "Flags"] {
+                    ui.label(RichText::new(title).strong());
+                }
+                ui.end_row();
+                for (index, sprite) in oam.chunks(4).enumerate() {
+                    let flags = sprite[3];
+                    ui.label(RichText::new(format!("{index:2}")).monospace());
+                    show_texture(ui, &self.sprites[index]);
+                    ui.label(RichText::new(format!("{:3}", sprite[0])).monospace());
+                    ui.label(RichText::new(format!("{:3}", sprite[1])).monospace());
+                    ui.label(RichText::new(format!("{:02X}", sprite[2])).monospace());
+                    ui.label(
+                        RichText::new(format!(
+                            "{:02X} {}{}{}{}",
+                            flags,
+                            if flags & 0x80 != 0 { "behind " } else { "" },
+                            if flags & 0x40 != 0 { "yflip " } else { "" },
+                            if flags & 0x20 != 0 { "xflip " } else { "" },
+                            if flags & 0x10 != 0 { "OBP1" } else { "OBP0" },
+                        ))
+                        .monospace(),
+                    );
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,217 @@
+// Runs a core with no window and no sound card, for CI and tests. It keeps the audio the core made
+// and can write the framebuffer out as a png and the audio as a wav, both written by hand here so
+// the cores don't need anything but std
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::emulator::{self, ButtonState, Emulator, Rgba};
+use crate::error::EmuError;
+
+pub struct Headless<'a> {
+    emulator: &'a mut dyn Emulator,
+    // ran since it started
+    pub frames: u64,
+    audio: Vec<f32>,
+}
+
+impl<'a> Headless<'a> {
+    pub fn new(emulator: &'a mut dyn Emulator) -> Headless<'a> {
+        Headless {
+            emulator,
+            frames: 0,
+            audio: vec![],
+        }
+    }
+
+    pub fn emulator(&mut self) -> &mut dyn Emulator {
+        self.emulator
+    }
+
+    pub fn set_input(&mut self, buttons: ButtonState) {
+        self.emulator.set_input(buttons);
+    }
+
+    // stops at the first error, frames only counts the ones that finished
+    pub fn run_frames(&mut self, count: u64) -> Result<(), EmuError> {
+        for _ in 0..count {
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    // stops early when done says so after a frame, returns whether it did
+    pub fn run_until(
+        &mut self,
+        max_frames: u64,
+        mut done: impl FnMut(&dyn Emulator) -> bool,
+    ) -> Result<bool, EmuError> {
+        for _ in 0..max_frames {
+            self.run_frame()?;
+            if done(self.emulator) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn run_frame(&mut self) -> Result<(), EmuError> {
+        let result = self.emulator.run_frame();
+        // whatever it made before it stopped is still worth keeping
+        self.audio.extend(self.emulator.audio_samples());
+        result?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn audio(&self) -> &[f32] {
+        &self.audio
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let (pixels, width, height) = self.emulator.framebuffer();
+        fs::write(path, encode_png(pixels, width, height))
+    }
+
+    pub fn save_wav(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, encode_wav(&self.audio))
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn png_chunk(png: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(tag);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+// rgba, 8 bits a channel. The pixels go in uncompressed (stored deflate blocks), framebuffers are
+// small enough that it doesn't matter
+pub fn encode_png(pixels: &[Rgba], width: usize, height: usize) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width * 4 + 1) * height);
+    for row in pixels.chunks(width).take(height) {
+        // no filter
+        raw.push(0);
+        for pixel in row {
+            raw.extend([pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        zlib.push(last as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // bit depth, color type rgba, compression, filter, no interlacing
+    header.extend([8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib);
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// mono 16 bit pcm at SAMPLE_RATE
+pub fn encode_wav(samples: &[f32]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = vec![];
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_len).to_le_bytes());
+    wav.extend(b"WAVE");
+    wav.extend(b"fmt ");
+    wav.extend(16u32.to_le_bytes());
+    // pcm, one channel
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(emulator::SAMPLE_RATE.to_le_bytes());
+    wav.extend((emulator::SAMPLE_RATE * 2).to_le_bytes());
+    // bytes a frame, bits a sample
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(data_len.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1., 1.) * i16::MAX as f32) as i16;
+        wav.extend(sample.to_le_bytes());
+    }
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8Emulator;
+
+    #[test]
+    fn encodes_png_and_wav() {
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+
+        let png = encode_png(&[Rgba::WHITE, Rgba::BLACK], 2, 1);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert!(png.ends_with(&[0xAE, 0x42, 0x60, 0x82]));
+        // IHDR says 2x1
+        assert_eq!(png[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+
+        let wav = encode_wav(&[0., 1., -1.]);
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(wav[44..], [0, 0, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn runs_without_a_window() {
+        // V0 = 5, sprite for 5, draw it, beep, loop
+        let rom = [0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0xF0, 0x18, 0x12, 0x08];
+        let mut chip8 = Chip8Emulator::new();
+        chip8.load_rom(&rom).unwrap();
+        let mut headless = Headless::new(&mut chip8);
+        let drew = headless.run_until(10, |emulator| {
+            let (pixels, _, _) = emulator.framebuffer();
+            pixels.contains(&Rgba::WHITE)
+        });
+        assert!(drew.unwrap());
+        assert_eq!(headless.frames, 1);
+        headless.run_frames(2).unwrap();
+        assert_eq!(headless.frames, 3);
+        assert!(headless.audio().iter().any(|sample| *sample != 0.));
+    }
+}
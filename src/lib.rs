@@ -0,0 +1,17 @@
+// The emulators as a library, so the play-us binary isn't the only thing that can run them. The
+// cores only need std and rand, the sdl frontend and the egui debuggers are behind features
+pub mod chip8;
+pub mod config;
+pub mod emulator;
+pub mod error;
+#[cfg(feature = "sdl")]
+pub mod frontend;
+pub mod gb;
+pub mod headless;
+pub mod memory_viewer;
+pub mod rewind;
+pub mod save_state;
+pub mod scheduler;
+pub mod speed;
+#[cfg(feature = "sdl")]
+pub mod video;
@@ -1,12 +1,19 @@
-use emulator::Emulator;
+use play_us::config::{self, Config};
+use play_us::emulator::Emulator;
+use play_us::error::{self, EmuError};
+use play_us::gb::disasm::Symbols;
+use play_us::headless::Headless;
+use play_us::save_state;
+use play_us::scheduler::FrameSync;
+use play_us::{chip8, frontend, gb};
 use std::env;
 use std::fs;
-use std::process::exit;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
 
-mod chip8;
-mod emulator;
-mod gb;
-mod video;
+// ten seconds
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
 
 #[derive(Clone, Copy)]
 enum Emulators {
@@ -23,7 +30,7 @@ impl ToString for Emulators {
     }
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
     let emulator_to_use;
     match args.get(1) {
@@ -33,7 +40,38 @@ fn main() {
                 println!("emulators avaiblable:");
                 println!("  chip8 - Chip8 emulator");
                 println!("  gb - GameBoy emulator");
-                return;
+                println!();
+                println!("options for every emulator:");
+                println!("  --rewind seconds - how far back backspace can rewind, 0 turns it off (default 10)");
+                println!("  --fast-forward times - how fast holding tab runs, 0 doesn't cap it (default 0)");
+                println!("  --sync timer|vsync|audio - what paces the frames (default timer)");
+                println!("  --headless - run without a window, as fast as it goes");
+                println!("  --frames n - how many frames --headless runs (default 600)");
+                println!("  --png file.png - with --headless, save the last frame");
+                println!("  --wav file.wav - with --headless, save all the audio");
+                println!();
+                println!("chip8 options:");
+                println!(
+                    "  --variant vip|chip48|schip|xochip - whose quirks to follow (default vip)"
+                );
+                println!("  --ipf n - instructions a frame, remembered for the rom (default 12)");
+                println!("  --hz n - instructions a second instead, remembered the same way");
+                println!("  --vip-timing - run as fast as the COSMAC VIP did, not a fixed speed");
+                println!("  --wrap-stack - keep going when the stack over or underflows");
+                println!("  --debug - run in a window with a live memory viewer");
+                println!();
+                println!("gb options:");
+                println!("  --model dmg|mgb|cgb - which game boy to behave like (default dmg)");
+                println!("  --accurate - emulate the hardware bugs of the chosen model");
+                println!("  --sym file.sym - RGBDS symbols to label the trace log and debugger");
+                println!("  --debug - open the debugger, with breakpoints, watchpoints and memory");
+                println!("  --gdb port - wait for gdb (target remote :port) instead of running");
+                println!();
+                println!("tools:");
+                println!("  disasm game.gb [--bank n] [--from addr] [--to addr] [--sym file.sym]");
+                return ExitCode::SUCCESS;
+            } else if arg1.eq("disasm") {
+                return disasm(&args[2..]);
             } else if arg1.eq("chip8") {
                 emulator_to_use = Emulators::Chip8;
             } else if arg1.eq("gb") {
@@ -42,17 +80,24 @@ fn main() {
                 println!("rom_path was not provided!");
                 println!("usage: emulator rom_path");
                 println!("\"help\" for more information, such as emulators available");
-                return;
+                return ExitCode::FAILURE;
             }
         }
         None => {
             println!("usage: emulator rom_path");
             println!("\"help\" for more information, such as emulators available");
-            return;
+            return ExitCode::FAILURE;
         }
     }
-    let rom_path = args[2].as_str();
-    let rom_type = rom_path.split(".").last().unwrap();
+    let Some(rom_path) = args.get(2) else {
+        println!("rom_path was not provided!");
+        println!("usage: emulator rom_path");
+        return ExitCode::FAILURE;
+    };
+    let rom_type = Path::new(rom_path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
     match (rom_type, emulator_to_use) {
         ("ch8", Emulators::Chip8) => {}
         ("gb", Emulators::GameBoy) => {}
@@ -61,20 +106,304 @@ fn main() {
                 ".{rom_type} is not a valid extension for {} to execute",
                 emulator_to_use.to_string()
             );
-            return;
+            return ExitCode::FAILURE;
         }
     }
-    let rom = fs::read(rom_path);
-    let rom = match rom {
+    let rom = match fs::read(rom_path) {
         Ok(bytes) => bytes,
-        Err(_) => {
-            println!("ROM path is invalid.");
-            exit(-1);
+        Err(err) => {
+            println!("couldn't read {rom_path}: {}", EmuError::from(err));
+            return ExitCode::FAILURE;
         }
     };
 
+    let options = &args[3..];
     match emulator_to_use {
-        Emulators::Chip8 => chip8::Chip8Emulator::new().run(&rom),
-        Emulators::GameBoy => gb::GameBoyEmulator::new().run(&rom),
+        Emulators::Chip8 => {
+            let variant = match option_value(options, "--variant") {
+                Some(name) => match chip8::quirks::Chip8Variant::from_name(name) {
+                    Some(variant) => variant,
+                    None => {
+                        println!("{name} is not a chip8 variant, try vip, chip48, schip or xochip");
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => chip8::quirks::Chip8Variant::CosmacVip,
+            };
+            let mut emulator = chip8::Chip8Emulator::with_variant(variant);
+            match chip8_hz(options, save_state::rom_hash(&rom)) {
+                Ok(Some(hz)) => emulator.hz = hz,
+                Ok(None) => {}
+                Err(err) => {
+                    println!("{err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+            emulator.vip_timing = options.iter().any(|option| option == "--vip-timing");
+            emulator.quirks.wrap_stack = options.iter().any(|option| option == "--wrap-stack");
+            if let Err(err) = emulator.load_rom(&rom) {
+                println!("couldn't load {rom_path}: {err}");
+                return ExitCode::FAILURE;
+            }
+            if options.iter().any(|option| option == "--headless") {
+                headless(&mut emulator, options)
+            } else if options.iter().any(|option| option == "--debug") {
+                exit_code(chip8::debug_ui::run(emulator))
+            } else {
+                // the frontend shows its own errors
+                let result = frontend::run(
+                    &mut emulator,
+                    &frontend::CHIP8_KEYS,
+                    &frontend_options(options),
+                );
+                match result {
+                    Ok(()) => ExitCode::SUCCESS,
+                    Err(_) => ExitCode::FAILURE,
+                }
+            }
+        }
+        Emulators::GameBoy => {
+            let model = match option_value(options, "--model") {
+                Some(name) => match gb::GbModel::from_name(name) {
+                    Some(model) => model,
+                    None => {
+                        println!("{name} is not a game boy model, try dmg, mgb or cgb");
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => gb::GbModel::Dmg,
+            };
+            let accurate = options.iter().any(|option| option == "--accurate");
+            let mut emulator = gb::GameBoyEmulator::with_model(model, accurate);
+            if let Some(path) = option_value(options, "--sym") {
+                match Symbols::load(path) {
+                    Ok(symbols) => emulator.load_symbols(symbols),
+                    Err(err) => println!("couldn't read {path}: {err}"),
+                }
+            }
+            if let Err(err) = emulator.load_rom(&rom) {
+                println!("couldn't load {rom_path}: {err}");
+                return ExitCode::FAILURE;
+            }
+            if let Some(port) = option_value(options, "--gdb") {
+                let Ok(port) = port.parse::<u16>() else {
+                    println!("{port} is not a port");
+                    return ExitCode::FAILURE;
+                };
+                gdb(emulator, port)
+            } else if options.iter().any(|option| option == "--headless") {
+                headless(&mut emulator, options)
+            } else if options.iter().any(|option| option == "--debug") {
+                exit_code(gb::debugger_ui::run(emulator))
+            } else {
+                let result = frontend::run(
+                    &mut emulator,
+                    &frontend::GAME_BOY_KEYS,
+                    &frontend_options(options),
+                );
+                match result {
+                    Ok(()) => ExitCode::SUCCESS,
+                    Err(_) => ExitCode::FAILURE,
+                }
+            }
+        }
+    }
+}
+
+fn exit_code(result: Result<(), EmuError>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            println!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// --hz or --ipf, which get remembered for the rom, or else what the config has for the rom or for
+// every rom. None leaves the default
+fn chip8_hz(options: &[String], rom_hash: u64) -> Result<Option<u32>, String> {
+    let mut config = match Config::load(config::CONFIG_FILE) {
+        Ok(config) => config,
+        Err(err) => return Err(format!("couldn't read {}: {err}", config::CONFIG_FILE)),
+    };
+    let rom_key = config::rom_key("chip8.hz", rom_hash);
+    let from_options = match (
+        option_value(options, "--hz"),
+        option_value(options, "--ipf"),
+    ) {
+        (Some(hz), _) => Some((hz, 1)),
+        (None, Some(ipf)) => Some((ipf, 60)),
+        (None, None) => None,
+    };
+    if let Some((value, per_second)) = from_options {
+        let hz = value
+            .parse::<u32>()
+            .ok()
+            .filter(|value| *value > 0)
+            .and_then(|value| value.checked_mul(per_second));
+        let Some(hz) = hz else {
+            return Err(format!("{value} isn't a speed, try a number like 12"));
+        };
+        config.set(&rom_key, hz);
+        match config.save() {
+            Ok(()) => println!("{hz} instructions a second from now on for this rom"),
+            Err(err) => println!("couldn't remember the speed: {err}"),
+        }
+        return Ok(Some(hz));
+    }
+    let saved = config.get(&rom_key).or_else(|| config.get("chip8.hz"));
+    match saved.map(|hz| hz.parse::<u32>()) {
+        Some(Ok(hz)) if hz > 0 => Ok(Some(hz)),
+        Some(_) => Err(format!("chip8.hz in {} isn't a speed", config::CONFIG_FILE)),
+        None => Ok(None),
+    }
+}
+
+// the options every emulator takes, anything that doesn't parse keeps its default
+fn frontend_options(options: &[String]) -> frontend::Options {
+    let mut frontend = frontend::Options::default();
+    if let Some(seconds) = option_value(options, "--rewind") {
+        match seconds.parse() {
+            Ok(seconds) => frontend.rewind_seconds = seconds,
+            Err(_) => println!("--rewind takes seconds, {seconds} isn't a number"),
+        }
+    }
+    if let Some(factor) = option_value(options, "--fast-forward") {
+        match factor.parse() {
+            Ok(factor) => frontend.fast_forward = factor,
+            Err(_) => {
+                println!("--fast-forward takes how many times faster, {factor} isn't a number")
+            }
+        }
+    }
+    if let Some(name) = option_value(options, "--sync") {
+        match FrameSync::parse(name) {
+            Some(sync) => frontend.sync = sync,
+            None => println!("--sync takes timer, vsync or audio, not {name}"),
+        }
+    }
+    frontend
+}
+
+// the screen and audio still get saved when the game stops early, they show how it got there
+fn headless(emulator: &mut dyn Emulator, options: &[String]) -> ExitCode {
+    let frames = match option_value(options, "--frames").map(str::parse) {
+        Some(Ok(frames)) => frames,
+        Some(Err(_)) => {
+            println!("--frames takes a number of frames");
+            return ExitCode::FAILURE;
+        }
+        None => DEFAULT_HEADLESS_FRAMES,
     };
+    let mut headless = Headless::new(emulator);
+    let mut code = ExitCode::SUCCESS;
+    match headless.run_frames(frames) {
+        Ok(()) => println!("ran {frames} frames"),
+        Err(err) => {
+            println!("stopped after {} frames", headless.frames);
+            println!("{}", error::report(&err, headless.emulator()));
+            code = ExitCode::FAILURE;
+        }
+    }
+    if let Some(path) = option_value(options, "--png") {
+        match headless.save_png(path) {
+            Ok(()) => println!("saved the screen to {path}"),
+            Err(err) => println!("couldn't save {path}: {err}"),
+        }
+    }
+    if let Some(path) = option_value(options, "--wav") {
+        match headless.save_wav(path) {
+            Ok(()) => println!("saved the audio to {path}"),
+            Err(err) => println!("couldn't save {path}: {err}"),
+        }
+    }
+    code
+}
+
+// only listens on localhost, there's no reason to let the whole network poke at the emulator
+fn gdb(emulator: gb::GameBoyEmulator, port: u16) -> ExitCode {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("couldn't listen on port {port}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("waiting for gdb on 127.0.0.1:{port}");
+    if let Err(err) = gb::gdb::GdbStub::new(emulator).serve(&listener) {
+        println!("gdb connection lost: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+// play-us disasm game.gb --bank 3 --from 0x4000
+fn disasm(args: &[String]) -> ExitCode {
+    let Some(rom_path) = args.first() else {
+        println!("usage: disasm game.gb [--bank n] [--from addr] [--to addr] [--sym file.sym]");
+        return ExitCode::FAILURE;
+    };
+    let options = &args[1..];
+    let rom = match fs::read(rom_path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            println!("couldn't read {rom_path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let number = |name: &str, default: u32, max: u32| match option_value(options, name) {
+        Some(value) => match parse_number(value) {
+            Some(number) if number <= max => Some(number),
+            Some(_) => {
+                println!("{value} is too big for {name}, it goes up to {max:#X}");
+                None
+            }
+            None => {
+                println!("{value} is not a valid number for {name}");
+                None
+            }
+        },
+        None => Some(default),
+    };
+    let Some(bank) = number("--bank", 0, 0xFF) else {
+        return ExitCode::FAILURE;
+    };
+    let bank = bank as u8;
+    let base = if bank == 0 { 0x0000 } else { 0x4000 };
+    let (Some(from), Some(to)) = (
+        number("--from", base, 0xFFFF),
+        number("--to", base + 0x4000, 0xFFFF),
+    ) else {
+        return ExitCode::FAILURE;
+    };
+    let symbols = match option_value(options, "--sym") {
+        Some(path) => match Symbols::load(path) {
+            Ok(symbols) => Some(symbols),
+            Err(err) => {
+                println!("couldn't read {path}: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    for line in gb::disasm::disassemble_bank(&rom, bank, from as u16, to, symbols.as_ref()) {
+        println!("{line}");
+    }
+    ExitCode::SUCCESS
+}
+
+// decimal, or hex with 0x or $ in front
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    text.parse().ok()
+}
+
+// the value right after a flag, like dmg in `--model dmg`
+fn option_value<'a>(options: &'a [String], name: &str) -> Option<&'a str> {
+    let position = options.iter().position(|option| option == name)?;
+    options.get(position + 1).map(|value| value.as_str())
 }
@@ -0,0 +1,59 @@
+// Hex view over the memory of any of the emulators. It reads through MemoryView every time it's
+// drawn instead of keeping a copy, so it follows the game while it runs, and only the rows that
+// are on screen get laid out. The window itself is in ui.rs, behind the egui feature
+#[cfg(feature = "egui")]
+mod ui;
+
+#[cfg(feature = "egui")]
+pub use ui::MemoryViewer;
+
+pub trait MemoryView {
+    fn size(&self) -> usize;
+    // reads without side effects, so looking at io registers doesn't change anything
+    fn peek(&self, address: usize) -> u8;
+    fn poke(&mut self, address: usize, value: u8);
+    // what lives at this address, like "WRAM" or "ROMX 03"
+    fn region(&self, address: usize) -> String;
+}
+
+// "3E 42" or "3e42"
+pub fn parse_pattern(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let high = pair[0].to_digit(16)?;
+            let low = pair[1].to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+pub fn find_pattern(memory: &[u8], pattern: &[u8], limit: usize) -> Vec<usize> {
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| *window == pattern)
+        .map(|(address, _)| address)
+        .take(limit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_patterns() {
+        assert_eq!(parse_pattern("3E 42"), Some(vec![0x3E, 0x42]));
+        assert_eq!(parse_pattern("3e4"), None);
+        assert_eq!(parse_pattern("zz"), None);
+
+        let memory = [0x00, 0x3E, 0x42, 0x3E, 0x42, 0x3E];
+        assert_eq!(find_pattern(&memory, &[0x3E, 0x42], 10), vec![1, 3]);
+        assert_eq!(find_pattern(&memory, &[0x3E], 2), vec![1, 3]);
+    }
+}
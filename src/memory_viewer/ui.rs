@@ -0,0 +1,191 @@
+// The egui side of the memory viewer
+use eframe::egui::{self, Color32, RichText};
+
+use super::{find_pattern, parse_pattern, MemoryView};
+
+const ROW: usize = 16;
+// searching for a single 00 would find half the memory
+const MAX_RESULTS: usize = 256;
+
+#[derive(Default)]
+pub struct MemoryViewer {
+    goto_text: String,
+    search_text: String,
+    results: Vec<usize>,
+    result_len: usize,
+    selected: Option<usize>,
+    edit_text: String,
+    // memory as it was before the last frame ran, to show what changed
+    previous: Vec<u8>,
+    scroll_to: Option<usize>,
+    error: Option<String>,
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn mono(text: impl Into<String>) -> RichText {
+    RichText::new(text).monospace()
+}
+
+impl MemoryViewer {
+    pub fn new() -> MemoryViewer {
+        MemoryViewer::default()
+    }
+
+    // call right before the emulator runs, everything different after that gets highlighted
+    pub fn snapshot(&mut self, memory: &dyn MemoryView) {
+        self.previous.clear();
+        self.previous
+            .extend((0..memory.size()).map(|address| memory.peek(address)));
+    }
+
+    fn changed(&self, memory: &dyn MemoryView, address: usize) -> bool {
+        self.previous
+            .get(address)
+            .is_some_and(|value| *value != memory.peek(address))
+    }
+
+    fn select(&mut self, memory: &dyn MemoryView, address: usize) {
+        self.selected = Some(address);
+        self.edit_text = format!("{:02X}", memory.peek(address));
+        self.scroll_to = Some(address);
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, memory: &mut dyn MemoryView) {
+        let size = memory.size();
+
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            let goto = ui.add(egui::TextEdit::singleline(&mut self.goto_text).desired_width(60.0));
+            let entered = goto.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Go").clicked() || entered {
+                match parse_hex(&self.goto_text).filter(|address| *address < size) {
+                    Some(address) => {
+                        self.select(memory, address);
+                        self.error = None;
+                    }
+                    None => self.error = Some(format!("{} is not an address", self.goto_text)),
+                }
+            }
+
+            ui.label("Search");
+            let search =
+                ui.add(egui::TextEdit::singleline(&mut self.search_text).desired_width(100.0));
+            let entered = search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Find").clicked() || entered {
+                match parse_pattern(&self.search_text) {
+                    Some(pattern) => {
+                        let bytes: Vec<u8> =
+                            (0..size).map(|address| memory.peek(address)).collect();
+                        self.results = find_pattern(&bytes, &pattern, MAX_RESULTS);
+                        self.result_len = pattern.len();
+                        if let Some(first) = self.results.first() {
+                            self.select(memory, *first);
+                        }
+                        self.error = None;
+                    }
+                    None => self.error = Some("search for hex bytes, like 3E 42".to_string()),
+                }
+            }
+        });
+
+        if !self.results.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(format!("{} found:", self.results.len()));
+                for address in self.results.clone() {
+                    if ui.small_button(format!("{address:04X}")).clicked() {
+                        self.select(memory, address);
+                    }
+                }
+            });
+        }
+
+        if let Some(address) = self.selected {
+            ui.horizontal(|ui| {
+                ui.label(mono(format!("{address:04X} {}", memory.region(address))));
+                let edit =
+                    ui.add(egui::TextEdit::singleline(&mut self.edit_text).desired_width(30.0));
+                let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Write").clicked() || entered {
+                    match u8::from_str_radix(self.edit_text.trim(), 16) {
+                        Ok(value) => {
+                            memory.poke(address, value);
+                            self.error = None;
+                        }
+                        Err(_) => self.error = Some(format!("{} is not a byte", self.edit_text)),
+                    }
+                }
+            });
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        ui.separator();
+        let header: String = (0..ROW).map(|column| format!(" {column:>2X}")).collect();
+        ui.label(mono(format!("{:13}{header}", "")));
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let rows = size.div_ceil(ROW);
+        let mut area = egui::ScrollArea::vertical().auto_shrink(false);
+        if let Some(address) = self.scroll_to.take() {
+            let spacing = ui.spacing().item_spacing.y;
+            area = area.vertical_scroll_offset((address / ROW) as f32 * (row_height + spacing));
+        }
+
+        let mut clicked = None;
+        area.show_rows(ui, row_height, rows, |ui, range| {
+            for row in range {
+                let start = row * ROW;
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 0.0;
+                    ui.label(mono(format!("{:8}{start:04X}:", memory.region(start))));
+                    for address in start..(start + ROW).min(size) {
+                        let value = memory.peek(address);
+                        let mut text = mono(format!(" {value:02X}"));
+                        if self.changed(memory, address) {
+                            text = text.color(Color32::RED);
+                        }
+                        if self
+                            .results
+                            .iter()
+                            .any(|found| (*found..*found + self.result_len).contains(&address))
+                        {
+                            text = text.background_color(Color32::DARK_GREEN);
+                        }
+                        if self.selected == Some(address) {
+                            text = text.background_color(Color32::DARK_BLUE);
+                        }
+                        let label = ui
+                            .add(egui::Label::new(text).sense(egui::Sense::click()))
+                            .on_hover_text(format!("{address:04X} = {value}"));
+                        if label.clicked() {
+                            clicked = Some(address);
+                        }
+                    }
+                    let ascii: String = (start..(start + ROW).min(size))
+                        .map(|address| match memory.peek(address) {
+                            value @ 0x20..=0x7E => value as char,
+                            _ => '.',
+                        })
+                        .collect();
+                    ui.label(mono(format!("  {ascii}")).color(Color32::GRAY));
+                });
+            }
+        });
+
+        if let Some(address) = clicked {
+            self.select(memory, address);
+            // clicking shouldn't make it jump around
+            self.scroll_to = None;
+        }
+    }
+}
@@ -0,0 +1,190 @@
+// Rewind keeps a save state of every frame so the game can run backwards. Only the newest one is
+// kept whole, every other frame is stored as the difference to the frame after it, which is mostly
+// zeros since a frame barely touches memory
+use std::collections::VecDeque;
+
+pub const DEFAULT_SECONDS: u32 = 10;
+// about, the game boy runs a little under 60
+const FRAMES_PER_SECOND: usize = 60;
+// never use more than this, even if the seconds asked for would
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+// a run of zeros this long ends a run of changed bytes, shorter ones are cheaper to keep inside it
+const MIN_ZERO_RUN: usize = 4;
+
+enum Snapshot {
+    // xor against the newer state, see compress
+    Delta(Vec<u8>),
+    // the state changed size, can't xor that
+    Full(Vec<u8>),
+}
+
+impl Snapshot {
+    fn len(&self) -> usize {
+        match self {
+            Snapshot::Delta(data) | Snapshot::Full(data) => data.len(),
+        }
+    }
+}
+
+pub struct Rewind {
+    // oldest first, each one turns the state after it into its own
+    snapshots: VecDeque<Snapshot>,
+    newest: Option<Vec<u8>>,
+    capacity: usize,
+    bytes: usize,
+}
+
+// The older state xor the newer one, written as [zeros u16][changed u16][changed bytes] runs
+fn compress(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let xor = |at: usize| older[at] ^ newer[at];
+    let mut out = vec![];
+    let mut at = 0;
+    while at < older.len() {
+        let start = at;
+        while at < older.len() && xor(at) == 0 && at - start < u16::MAX as usize {
+            at += 1;
+        }
+        let zeros = at - start;
+
+        let start = at;
+        while at < older.len() && at - start < u16::MAX as usize {
+            let end = (at + MIN_ZERO_RUN).min(older.len());
+            if (at..end).all(|at| xor(at) == 0) {
+                break;
+            }
+            at += 1;
+        }
+        out.extend((zeros as u16).to_le_bytes());
+        out.extend(((at - start) as u16).to_le_bytes());
+        out.extend((start..at).map(xor));
+    }
+    out
+}
+
+fn decompress(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut older = newer.to_vec();
+    let mut at = 0;
+    let mut data = delta;
+    while data.len() >= 4 {
+        let zeros = u16::from_le_bytes([data[0], data[1]]) as usize;
+        let changed = u16::from_le_bytes([data[2], data[3]]) as usize;
+        at += zeros;
+        for (byte, xor) in older[at..at + changed]
+            .iter_mut()
+            .zip(&data[4..4 + changed])
+        {
+            *byte ^= xor;
+        }
+        at += changed;
+        data = &data[4 + changed..];
+    }
+    older
+}
+
+impl Default for Rewind {
+    fn default() -> Rewind {
+        Rewind::new(DEFAULT_SECONDS)
+    }
+}
+
+impl Rewind {
+    // 0 seconds turns rewind off
+    pub fn new(seconds: u32) -> Rewind {
+        Rewind {
+            snapshots: VecDeque::new(),
+            newest: None,
+            capacity: seconds as usize * FRAMES_PER_SECOND,
+            bytes: 0,
+        }
+    }
+
+    // once per frame, with the state before the frame runs
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        // nothing ran, like right after rewinding
+        if self.newest.as_ref() == Some(&state) {
+            return;
+        }
+        if let Some(newest) = self.newest.take() {
+            let snapshot = if newest.len() == state.len() {
+                Snapshot::Delta(compress(&newest, &state))
+            } else {
+                Snapshot::Full(newest)
+            };
+            self.bytes += snapshot.len();
+            self.snapshots.push_back(snapshot);
+        }
+        self.newest = Some(state);
+
+        while self.snapshots.len() > self.capacity || self.bytes > MAX_BYTES {
+            match self.snapshots.pop_front() {
+                Some(snapshot) => self.bytes -= snapshot.len(),
+                None => break,
+            }
+        }
+    }
+
+    // the state one frame further back, None when there's nothing older left
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let snapshot = self.snapshots.pop_back()?;
+        self.bytes -= snapshot.len();
+        let newest = self.newest.as_ref()?;
+        let older = match snapshot {
+            Snapshot::Delta(delta) => decompress(newest, &delta),
+            Snapshot::Full(state) => state,
+        };
+        self.newest = Some(older.clone());
+        Some(older)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_differences() {
+        let newer: Vec<u8> = (0..1000).map(|at| (at % 251) as u8).collect();
+        let mut older = newer.clone();
+        older[3] = 0xFF;
+        older[4] = 0xFE;
+        older[900] = 0;
+        let delta = compress(&older, &newer);
+        assert!(delta.len() < 20);
+        assert_eq!(decompress(&newer, &delta), older);
+        assert_eq!(decompress(&newer, &compress(&newer, &newer)), newer);
+
+        let noise: Vec<u8> = (0..70000).map(|at| (at * 7 % 256) as u8).collect();
+        assert_eq!(
+            decompress(&newer.repeat(70), &compress(&noise, &newer.repeat(70))),
+            noise
+        );
+    }
+
+    #[test]
+    fn goes_back_in_order() {
+        let mut rewind = Rewind::new(1);
+        for frame in 0..100u8 {
+            rewind.push(vec![frame; 16]);
+        }
+        assert_eq!(rewind.pop(), Some(vec![98; 16]));
+        assert_eq!(rewind.pop(), Some(vec![97; 16]));
+        rewind.push(vec![97; 16]);
+        rewind.push(vec![200; 32]);
+        assert_eq!(rewind.pop(), Some(vec![97; 16]));
+        // a second worth of frames, the newest isn't counted since it's where we are
+        let mut left = 0;
+        while let Some(state) = rewind.pop() {
+            left += 1;
+            assert_eq!(state, vec![97 - left; 16]);
+        }
+        assert_eq!(left, 60 - 2);
+
+        let mut off = Rewind::new(0);
+        off.push(vec![1]);
+        off.push(vec![2]);
+        assert_eq!(off.pop(), None);
+    }
+}
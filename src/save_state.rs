@@ -0,0 +1,350 @@
+// Save states shared by all the emulators. A state is a small header followed by tagged chunks:
+//
+//   "PLAYUSST" | format version u16 | machine name | rom hash u64 | chunk*
+//   chunk = tag [u8; 4] | length u32 | data
+//
+// Everything is little endian. Readers look chunks up by tag and skip the ones they don't know,
+// so adding a chunk doesn't break older states, anything else bumps FORMAT_VERSION
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const MAGIC: &[u8; 8] = b"PLAYUSST";
+// 2: the chip8 saves its real pc and stack, not offsets from where the rom starts
+pub const FORMAT_VERSION: u16 = 2;
+// states live in here, named after the rom hash so every game gets its own slots
+const STATE_DIR: &str = "states";
+
+#[derive(Debug)]
+pub enum StateError {
+    NotAState,
+    UnsupportedVersion(u16),
+    WrongMachine(String),
+    // the state was made with another rom, loading it would just crash the game
+    WrongRom,
+    MissingChunk(String),
+    // a chunk ended before all its data was read, or an array had the wrong size
+    Corrupt(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {version} is newer than this emulator ({FORMAT_VERSION})"
+            ),
+            StateError::WrongMachine(machine) => write!(f, "save state is from the {machine}"),
+            StateError::WrongRom => write!(f, "save state is from a different rom"),
+            StateError::MissingChunk(tag) => write!(f, "save state has no {tag} chunk"),
+            StateError::Corrupt(tag) => write!(f, "save state chunk {tag} is corrupt"),
+            StateError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<io::Error> for StateError {
+    fn from(err: io::Error) -> StateError {
+        StateError::Io(err)
+    }
+}
+
+// FNV-1a, plenty to tell roms apart without pulling a crate in
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF29CE484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001B3)
+    })
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    data: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    // length first, so loading can tell when it doesn't fit
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend(bytes);
+    }
+}
+
+impl StateWriter {
+    pub fn new(machine: &str, rom_hash: u64) -> StateWriter {
+        let mut header = Chunk::default();
+        header.data.extend(MAGIC);
+        header.u16(FORMAT_VERSION);
+        header.bytes(machine.as_bytes());
+        header.u64(rom_hash);
+        StateWriter { data: header.data }
+    }
+
+    pub fn chunk(&mut self, tag: &[u8; 4], write: impl FnOnce(&mut Chunk)) {
+        let mut chunk = Chunk::default();
+        write(&mut chunk);
+        self.data.extend(tag);
+        self.data.extend((chunk.data.len() as u32).to_le_bytes());
+        self.data.extend(chunk.data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct ChunkReader<'a> {
+    tag: &'a [u8; 4],
+    data: &'a [u8],
+}
+
+impl<'a> ChunkReader<'a> {
+    fn corrupt(&self) -> StateError {
+        StateError::Corrupt(String::from_utf8_lossy(self.tag).to_string())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(self.corrupt());
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    // for fixed size things like memory, a state with a different size is broken
+    pub fn bytes_into(&mut self, into: &mut [u8]) -> Result<(), StateError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != into.len() {
+            return Err(self.corrupt());
+        }
+        into.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+pub struct StateReader<'a> {
+    // of the emulator that saved it, never newer than FORMAT_VERSION
+    pub version: u16,
+    chunks: Vec<(&'a [u8; 4], &'a [u8])>,
+}
+
+impl<'a> StateReader<'a> {
+    // checks the header before anything gets touched
+    pub fn parse(data: &'a [u8], machine: &str, rom_hash: u64) -> Result<Self, StateError> {
+        let Some(rest) = data.strip_prefix(MAGIC) else {
+            return Err(StateError::NotAState);
+        };
+        let mut header = ChunkReader {
+            tag: b"HEAD",
+            data: rest,
+        };
+        let version = header.u16().map_err(|_| StateError::NotAState)?;
+        if version > FORMAT_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let state_machine = String::from_utf8_lossy(header.bytes()?).to_string();
+        if state_machine != machine {
+            return Err(StateError::WrongMachine(state_machine));
+        }
+        if header.u64()? != rom_hash {
+            return Err(StateError::WrongRom);
+        }
+
+        let mut chunks = vec![];
+        let mut rest = header.data;
+        while !rest.is_empty() {
+            let mut chunk = ChunkReader {
+                tag: b"HEAD",
+                data: rest,
+            };
+            let tag: &[u8; 4] = chunk.take(4)?.try_into().unwrap();
+            chunk.tag = tag;
+            let len = chunk.u32()? as usize;
+            chunks.push((tag, chunk.take(len)?));
+            rest = chunk.data;
+        }
+        Ok(StateReader { version, chunks })
+    }
+
+    pub fn chunk(&self, tag: &'a [u8; 4]) -> Result<ChunkReader<'a>, StateError> {
+        self.chunks
+            .iter()
+            .find(|(at, _)| *at == tag)
+            .map(|(tag, data)| ChunkReader { tag, data })
+            .ok_or_else(|| StateError::MissingChunk(String::from_utf8_lossy(tag).to_string()))
+    }
+}
+
+pub trait SaveState {
+    fn rom_hash(&self) -> u64;
+    fn save_state(&self) -> Vec<u8>;
+    // refuses states from other roms and leaves the machine alone when it fails
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError>;
+
+    fn save_slot(&self, slot: u8) {
+        match write_slot(self.rom_hash(), slot, &self.save_state()) {
+            Ok(path) => println!("saved state {slot} to {}", path.display()),
+            Err(err) => println!("couldn't save state {slot}: {err}"),
+        }
+    }
+
+    // the few bytes a game keeps between runs on its own, None for the ones that don't. The core
+    // only keeps them in memory, whoever runs it reads and writes the file
+    fn flags(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_flags(&mut self, _flags: &[u8]) {}
+
+    // before the first frame, no file is just a game that never saved any
+    fn read_flags_file(&mut self) {
+        if self.flags().is_none() {
+            return;
+        }
+        if let Ok(flags) = read_flags(self.rom_hash()) {
+            self.load_flags(&flags);
+        }
+    }
+
+    fn write_flags_file(&self) {
+        if let Some(flags) = self.flags() {
+            if let Err(err) = write_flags(self.rom_hash(), &flags) {
+                println!("couldn't save the flags: {err}");
+            }
+        }
+    }
+
+    fn load_slot(&mut self, slot: u8) {
+        let loaded = read_slot(self.rom_hash(), slot)
+            .map_err(StateError::from)
+            .and_then(|state| self.load_state(&state));
+        match loaded {
+            Ok(()) => println!("loaded state {slot}"),
+            Err(err) => println!("couldn't load state {slot}: {err}"),
+        }
+    }
+}
+
+fn slot_path(rom_hash: u64, slot: u8) -> PathBuf {
+    PathBuf::from(STATE_DIR).join(format!("{rom_hash:016x}-{slot}.state"))
+}
+
+pub fn write_slot(rom_hash: u64, slot: u8, state: &[u8]) -> io::Result<PathBuf> {
+    let path = slot_path(rom_hash, slot);
+    fs::create_dir_all(STATE_DIR)?;
+    fs::write(&path, state)?;
+    Ok(path)
+}
+
+pub fn read_slot(rom_hash: u64, slot: u8) -> io::Result<Vec<u8>> {
+    fs::read(slot_path(rom_hash, slot))
+}
+
+// the few bytes a game keeps between runs on its own, like the schip's RPL flags with the high
+// scores in them. They live next to the states
+fn flags_path(rom_hash: u64) -> PathBuf {
+    PathBuf::from(STATE_DIR).join(format!("{rom_hash:016x}.flags"))
+}
+
+pub fn write_flags(rom_hash: u64, flags: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(STATE_DIR)?;
+    fs::write(flags_path(rom_hash), flags)
+}
+
+pub fn read_flags(rom_hash: u64) -> io::Result<Vec<u8>> {
+    fs::read(flags_path(rom_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_chunks() {
+        let mut writer = StateWriter::new("test", 42);
+        writer.chunk(b"ONE ", |chunk| {
+            chunk.u16(0x1234);
+            chunk.bytes(&[1, 2, 3]);
+        });
+        writer.chunk(b"TWO ", |chunk| chunk.bool(true));
+        let state = writer.finish();
+
+        let reader = StateReader::parse(&state, "test", 42).unwrap();
+        let mut two = reader.chunk(b"TWO ").unwrap();
+        assert!(two.bool().unwrap());
+        assert!(matches!(two.u8(), Err(StateError::Corrupt(_))));
+        let mut one = reader.chunk(b"ONE ").unwrap();
+        assert_eq!(one.u16().unwrap(), 0x1234);
+        let mut three = [0; 2];
+        assert!(one.bytes_into(&mut three).is_err());
+        assert!(matches!(
+            reader.chunk(b"NOPE"),
+            Err(StateError::MissingChunk(_))
+        ));
+
+        assert!(matches!(
+            StateReader::parse(&state, "test", 43),
+            Err(StateError::WrongRom)
+        ));
+        assert!(matches!(
+            StateReader::parse(&state, "other", 42),
+            Err(StateError::WrongMachine(_))
+        ));
+        assert!(matches!(
+            StateReader::parse(b"garbage", "test", 42),
+            Err(StateError::NotAState)
+        ));
+    }
+}
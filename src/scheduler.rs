@@ -0,0 +1,167 @@
+// Frame pacing shared by the main loops. Every frame is due a frame time after the last one was
+// due, not after it finished, so the frame rate doesn't drift, and when the emulator can't keep up
+// the frames it missed get reported instead of run in a hurry
+use std::hint;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// sleep wakes up late by a millisecond or so, this last bit gets spun instead
+const SPIN: Duration = Duration::from_micros(1500);
+// dropped frames are added up and reported this often, so a slow computer doesn't flood the output
+const REPORT_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameSync {
+    // sleeps for the frame time
+    Timer,
+    // presenting the screen waits for the monitor, so the frame rate is the monitor's
+    Vsync,
+    // follows the samples the audio device played, so the sound never runs dry or piles up
+    Audio,
+}
+
+impl FrameSync {
+    pub fn parse(name: &str) -> Option<FrameSync> {
+        match name {
+            "timer" => Some(FrameSync::Timer),
+            "vsync" => Some(FrameSync::Vsync),
+            "audio" => Some(FrameSync::Audio),
+            _ => None,
+        }
+    }
+}
+
+// samples played by an audio callback, it counts them itself as it fills the buffer
+#[derive(Clone, Default)]
+pub struct AudioClock(Arc<AtomicU64>);
+
+impl AudioClock {
+    pub fn advance(&self, samples: usize) {
+        self.0.fetch_add(samples as u64, Ordering::Relaxed);
+    }
+
+    fn samples(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+enum Clock {
+    Timer(Instant),
+    Audio(AudioClock, f64),
+}
+
+impl Clock {
+    fn now(&self) -> Duration {
+        match self {
+            Clock::Timer(start) => start.elapsed(),
+            Clock::Audio(clock, sample_rate) => {
+                Duration::from_secs_f64(clock.samples() as f64 / sample_rate)
+            }
+        }
+    }
+}
+
+pub struct Scheduler {
+    sync: FrameSync,
+    clock: Clock,
+    // when the next frame is due, on the clock
+    next: Duration,
+    // since the last report
+    dropped: u64,
+    last_report: Instant,
+}
+
+impl Scheduler {
+    // syncing to audio needs a clock from audio_clock, until then it uses the timer
+    pub fn new(sync: FrameSync) -> Scheduler {
+        Scheduler {
+            sync,
+            clock: Clock::Timer(Instant::now()),
+            next: Duration::ZERO,
+            dropped: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn audio_clock(&mut self, clock: AudioClock, sample_rate: i32) {
+        if self.sync == FrameSync::Audio {
+            self.clock = Clock::Audio(clock, sample_rate as f64);
+            self.next = self.clock.now();
+        }
+    }
+
+    // once a frame, after it ran. frame_time comes from Speed, None doesn't wait at all
+    pub fn wait(&mut self, frame_time: Option<Duration>) {
+        let now = self.clock.now();
+        let Some(frame_time) = frame_time else {
+            self.next = now;
+            return;
+        };
+
+        self.next += frame_time;
+        // more than a whole frame late, catching up would just run the game too fast for a bit
+        if now > self.next + frame_time {
+            let behind = now - self.next;
+            self.dropped += (behind.as_secs_f64() / frame_time.as_secs_f64()) as u64;
+            self.next = now;
+        }
+        self.report();
+
+        match &self.clock {
+            // present already waited, it just can't get ahead of the game
+            _ if self.sync == FrameSync::Vsync => self.next = self.next.max(now),
+            Clock::Timer(start) => sleep_until(*start + self.next),
+            // the callback only counts whole buffers, so there's no point in spinning
+            Clock::Audio(..) => {
+                while self.clock.now() < self.next {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+    }
+
+    fn report(&mut self) {
+        if self.dropped > 0 && self.last_report.elapsed() >= REPORT_EVERY {
+            println!("running behind, dropped {} frames", self.dropped);
+            self.dropped = 0;
+            self.last_report = Instant::now();
+        }
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now + SPIN {
+        thread::sleep(deadline - now - SPIN);
+    }
+    while Instant::now() < deadline {
+        hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paces_and_drops_frames() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(FrameSync::Timer);
+        for _ in 0..3 {
+            scheduler.wait(Some(Duration::from_millis(5)));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(15));
+        assert_eq!(scheduler.dropped, 0);
+
+        thread::sleep(Duration::from_millis(20));
+        scheduler.wait(Some(Duration::from_millis(5)));
+        assert!(scheduler.dropped >= 3);
+
+        let before = Instant::now();
+        scheduler.wait(None);
+        scheduler.wait(None);
+        assert!(before.elapsed() < Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,124 @@
+// Fast-forward, slow motion, pause and frame advance, shared by the emulators' main loops. The loop
+// asks it whether to run a frame and how long one lasts, it doesn't care what a frame is
+use std::time::Duration;
+
+// how much slower slow motion runs
+const SLOW_MOTION: f64 = 0.25;
+
+#[derive(Clone, Copy)]
+pub struct Speed {
+    // frames a second at normal speed
+    frame_rate: f64,
+    // how many times faster fast-forward runs, None goes as fast as it can
+    fast_forward: Option<f64>,
+    fast_forwarding: bool,
+    slow_motion: bool,
+    paused: bool,
+    // a frame to run even though it's paused
+    advance: bool,
+}
+
+impl Speed {
+    pub fn new(frame_rate: f64) -> Speed {
+        Speed {
+            frame_rate,
+            fast_forward: None,
+            fast_forwarding: false,
+            slow_motion: false,
+            paused: false,
+            advance: false,
+        }
+    }
+
+    // 0 lets fast-forward run uncapped
+    pub fn set_fast_forward(&mut self, factor: f64) {
+        self.fast_forward = if factor > 0. { Some(factor) } else { None };
+    }
+
+    // held down, so it's on for as long as the key is
+    pub fn set_fast_forwarding(&mut self, fast_forwarding: bool) {
+        self.fast_forwarding = fast_forwarding;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // advancing a running game pauses it first, like most emulators do
+    pub fn advance(&mut self) {
+        if self.paused {
+            self.advance = true;
+        }
+        self.paused = true;
+    }
+
+    // times normal speed, None when fast-forwarding uncapped
+    pub fn factor(&self) -> Option<f64> {
+        if self.fast_forwarding {
+            self.fast_forward
+        } else if self.slow_motion {
+            Some(SLOW_MOTION)
+        } else {
+            Some(1.)
+        }
+    }
+
+    // false while paused, except for the one frame after frame advance
+    pub fn run_frame(&mut self) -> bool {
+        if self.paused {
+            return std::mem::take(&mut self.advance);
+        }
+        true
+    }
+
+    // None when it shouldn't wait at all. Paused still waits a normal frame so it doesn't spin
+    pub fn frame_time(&self) -> Option<Duration> {
+        let factor = if self.paused { Some(1.) } else { self.factor() };
+        factor.map(|factor| Duration::from_secs_f64(1. / (self.frame_rate * factor)))
+    }
+
+    // what to multiply the audio pitch by, like playing a tape faster. None mutes it, for pauses
+    // and for uncapped fast-forward where it would just be noise
+    pub fn audio_rate(&self) -> Option<f32> {
+        if self.paused {
+            return None;
+        }
+        self.factor().map(|factor| factor as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_speed() {
+        let mut speed = Speed::new(60.);
+        assert_eq!(speed.frame_time(), Some(Duration::from_secs_f64(1. / 60.)));
+
+        speed.set_fast_forwarding(true);
+        assert_eq!(speed.frame_time(), None);
+        assert_eq!(speed.audio_rate(), None);
+        speed.set_fast_forward(2.);
+        assert_eq!(speed.frame_time(), Some(Duration::from_secs_f64(1. / 120.)));
+        speed.set_fast_forwarding(false);
+
+        speed.toggle_slow_motion();
+        assert_eq!(speed.audio_rate(), Some(0.25));
+
+        assert!(speed.run_frame());
+        speed.advance();
+        assert!(!speed.run_frame());
+        speed.advance();
+        assert!(speed.run_frame());
+        assert!(!speed.run_frame());
+        assert_eq!(speed.frame_time(), Some(Duration::from_secs_f64(1. / 60.)));
+
+        speed.toggle_pause();
+        assert!(speed.run_frame());
+    }
+}
@@ -1,35 +1,70 @@
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::{self, render::Canvas, video::Window, AudioSubsystem, EventPump};
 
+use crate::error::EmuError;
+
 pub struct Screen {
     pub canvas: Canvas<Window>,
     pub event_pump: EventPump,
-    pub audio: AudioSubsystem
+    pub audio: AudioSubsystem,
 }
 
 impl Screen {
-    pub fn new(width: Option<u32>, height: Option<u32>) -> Screen {
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-        let audio_subsystem = sdl_context.audio().unwrap();
+    // with vsync, present waits for the monitor to refresh
+    pub fn new(width: Option<u32>, height: Option<u32>, vsync: bool) -> Result<Screen, EmuError> {
+        let sdl_context = sdl2::init().map_err(EmuError::Sdl)?;
+        let video_subsystem = sdl_context.video().map_err(EmuError::Sdl)?;
+        let audio_subsystem = sdl_context.audio().map_err(EmuError::Sdl)?;
 
         let window = video_subsystem
             .window("SDL2", width.unwrap_or(640), height.unwrap_or(480))
             .position_centered()
             .build()
-            .map_err(|e| e.to_string()).unwrap();
+            .map_err(|e| EmuError::Sdl(e.to_string()))?;
 
-        let mut canvas = window
-            .into_canvas()
-            .accelerated()
-            .build()
-            .map_err(|e| e.to_string()).unwrap();
+        let mut canvas = window.into_canvas().accelerated();
+        if vsync {
+            canvas = canvas.present_vsync();
+        }
+        let canvas = canvas.build().map_err(|e| EmuError::Sdl(e.to_string()))?;
 
-        let mut event_pump = sdl_context.event_pump().unwrap();
+        let event_pump = sdl_context.event_pump().map_err(EmuError::Sdl)?;
 
-        Screen {
+        Ok(Screen {
             canvas,
             event_pump,
-            audio: audio_subsystem
-        }
+            audio: audio_subsystem,
+        })
+    }
+}
+
+// held down to run the game backwards
+pub const REWIND_KEY: Keycode = Keycode::Backspace;
+// held down to fast-forward, the rest toggle
+pub const FAST_FORWARD_KEY: Keycode = Keycode::Tab;
+pub const SLOW_MOTION_KEY: Keycode = Keycode::Backquote;
+pub const PAUSE_KEY: Keycode = Keycode::P;
+pub const FRAME_ADVANCE_KEY: Keycode = Keycode::Period;
+// right after the save state slots
+pub const RESET_KEY: Keycode = Keycode::F5;
+
+pub enum StateHotkey {
+    Save(u8),
+    Load(u8),
+}
+
+// F1-F4 load a save state slot, shift+F1-F4 save to it, like snes9x does
+pub fn state_hotkey(keycode: Keycode, keymod: Mod) -> Option<StateHotkey> {
+    let slot = match keycode {
+        Keycode::F1 => 1,
+        Keycode::F2 => 2,
+        Keycode::F3 => 3,
+        Keycode::F4 => 4,
+        _ => return None,
+    };
+    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        Some(StateHotkey::Save(slot))
+    } else {
+        Some(StateHotkey::Load(slot))
     }
 }